@@ -0,0 +1,343 @@
+//! Copyright © 2024 ChefKiss Inc. Licensed under the Thou Shalt Not Profit License version 1.5.
+//! See LICENSE for details.
+
+// Container layouts from Linux's `amdgpu_ucode.h`. Everything is little endian.
+
+const COMMON_HEADER_SIZE: u32 = 0x20;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct UcodeInfo {
+    pub ip_version: (u16, u16),
+    pub ucode_version: u32,
+    pub feature_version: u32,
+    /// Jump table offset in dwords from the start of the ucode.
+    pub jt_offset: u32,
+    /// Jump table size in dwords.
+    pub jt_size: u32,
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(
+        data.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        data.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+/// Reads the versions from an amdgpu header at the start of `data`, for drivers that embed blobs with one. The
+/// feature version follows the common header in every GC, SDMA and RLC header version.
+pub fn read_header(data: &[u8]) -> Option<UcodeInfo> {
+    let size = read_u32(data, 0)? as usize;
+    let header_size = read_u32(data, 4)?;
+    let ucode_size = read_u32(data, 0x14)?;
+    let ucode_offset = read_u32(data, 0x18)?;
+    if size != data.len()
+        || !(COMMON_HEADER_SIZE..=0x400).contains(&header_size)
+        || !(1..=2).contains(&read_u16(data, 8)?)
+        || u64::from(ucode_offset) + u64::from(ucode_size) > size as u64
+    {
+        return None;
+    }
+    Some(UcodeInfo {
+        ip_version: (read_u16(data, 0xC)?, read_u16(data, 0xE)?),
+        ucode_version: read_u32(data, 0x10)?,
+        feature_version: if header_size > COMMON_HEADER_SIZE {
+            read_u32(data, COMMON_HEADER_SIZE as usize)?
+        } else {
+            0
+        },
+        ..Default::default()
+    })
+}
+
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn u16(&mut self, v: u16) {
+        self.0.extend_from_slice(&v.to_le_bytes());
+    }
+
+    fn u32(&mut self, v: u32) {
+        self.0.extend_from_slice(&v.to_le_bytes());
+    }
+
+    fn u32s(&mut self, v: &[u32]) {
+        for &v in v {
+            self.u32(v);
+        }
+    }
+}
+
+fn len32(data: &[u8]) -> u32 {
+    data.len().try_into().expect("firmware larger than 4GiB")
+}
+
+/// Emits `common_firmware_header` followed by `ip_fields`, the ucode and then `payloads`, in order.
+fn build(
+    header_version: (u16, u16),
+    info: &UcodeInfo,
+    ip_fields: &[u32],
+    ucode: &[u8],
+    payloads: &[&[u8]],
+) -> Vec<u8> {
+    let header_size = COMMON_HEADER_SIZE + 4 * ip_fields.len() as u32;
+    let size = header_size + len32(ucode) + payloads.iter().map(|v| len32(v)).sum::<u32>();
+    let mut w = Writer(Vec::with_capacity(size as usize));
+    w.u32(size);
+    w.u32(header_size);
+    w.u16(header_version.0);
+    w.u16(header_version.1);
+    w.u16(info.ip_version.0);
+    w.u16(info.ip_version.1);
+    w.u32(info.ucode_version);
+    w.u32(len32(ucode));
+    w.u32(header_size);
+    w.u32(crc32fast::hash(ucode));
+    w.u32s(ip_fields);
+    w.0.extend_from_slice(ucode);
+    for payload in payloads {
+        w.0.extend_from_slice(payload);
+    }
+    w.0
+}
+
+/// `gfx_firmware_header_v1_0`, used by PFP, ME, CE and MEC.
+pub fn gfx_v1_0(ucode: &[u8], info: &UcodeInfo) -> Vec<u8> {
+    build(
+        (1, 0),
+        info,
+        &[info.feature_version, info.jt_offset, info.jt_size],
+        ucode,
+        &[],
+    )
+}
+
+/// `sdma_firmware_header_v1_0`.
+pub fn sdma_v1_0(ucode: &[u8], info: &UcodeInfo) -> Vec<u8> {
+    build(
+        (1, 0),
+        info,
+        &[info.feature_version, 0, info.jt_offset, info.jt_size],
+        ucode,
+        &[],
+    )
+}
+
+/// A save/restore list blob embedded by `rlc_firmware_header_v2_1`.
+#[derive(Debug, Clone, Default)]
+pub struct RlcList {
+    pub ucode_version: u32,
+    pub feature_version: u32,
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RlcFirmware {
+    pub ucode: Vec<u8>,
+    pub save_and_restore_offset: u32,
+    pub clear_state_descriptor_offset: u32,
+    pub avail_scratch_ram_locations: u32,
+    pub master_pkt_description_offset: u32,
+    pub reg_restore_list_size: u32,
+    pub reg_list_format_start: u32,
+    pub reg_list_format_separate_start: u32,
+    pub starting_offsets_start: u32,
+    pub reg_list_format_direct_reg_list_length: u32,
+    pub reg_list_format: Vec<u8>,
+    pub reg_list: Vec<u8>,
+    pub reg_list_format_separate: Vec<u8>,
+    pub reg_list_separate: Vec<u8>,
+    pub cntl: Option<RlcList>,
+    pub gpm: Option<RlcList>,
+    pub srm: Option<RlcList>,
+    pub iram: Option<Vec<u8>>,
+    pub dram: Option<Vec<u8>>,
+}
+
+/// `rlc_firmware_header_v2_0`, `v2_1` when any save/restore list is present and `v2_2` when IRAM/DRAM ucode is.
+pub fn rlc_v2(fw: &RlcFirmware, info: &UcodeInfo) -> Vec<u8> {
+    let empty = RlcList::default();
+    let has_lists = fw.cntl.is_some() || fw.gpm.is_some() || fw.srm.is_some();
+    let has_ram = fw.iram.is_some() || fw.dram.is_some();
+    let (minor, ip_field_count) = match (has_lists, has_ram) {
+        (_, true) => (2, 19 + 13 + 4),
+        (true, false) => (1, 19 + 13),
+        (false, false) => (0, 19),
+    };
+    let mut offset = COMMON_HEADER_SIZE + 4 * ip_field_count + len32(&fw.ucode);
+    let mut payloads: Vec<&[u8]> = Vec::new();
+    let mut place = |data: &'_ [u8]| {
+        let ret = offset;
+        offset += len32(data);
+        ret
+    };
+    let reg_list_format_off = place(&fw.reg_list_format);
+    let reg_list_off = place(&fw.reg_list);
+    let reg_list_format_separate_off = place(&fw.reg_list_format_separate);
+    let reg_list_separate_off = place(&fw.reg_list_separate);
+    payloads.extend([
+        fw.reg_list_format.as_slice(),
+        &fw.reg_list,
+        &fw.reg_list_format_separate,
+        &fw.reg_list_separate,
+    ]);
+    let mut fields = vec![
+        info.feature_version,
+        info.jt_offset,
+        info.jt_size,
+        fw.save_and_restore_offset,
+        fw.clear_state_descriptor_offset,
+        fw.avail_scratch_ram_locations,
+        fw.master_pkt_description_offset,
+        fw.reg_restore_list_size,
+        fw.reg_list_format_start,
+        fw.reg_list_format_separate_start,
+        fw.starting_offsets_start,
+        len32(&fw.reg_list_format),
+        reg_list_format_off,
+        len32(&fw.reg_list),
+        reg_list_off,
+        len32(&fw.reg_list_format_separate),
+        reg_list_format_separate_off,
+        len32(&fw.reg_list_separate),
+        reg_list_separate_off,
+    ];
+    if minor >= 1 {
+        fields.push(fw.reg_list_format_direct_reg_list_length);
+        for list in [&fw.cntl, &fw.gpm, &fw.srm] {
+            let list = list.as_ref().unwrap_or(&empty);
+            let off = place(&list.data);
+            fields.extend([
+                list.ucode_version,
+                list.feature_version,
+                len32(&list.data),
+                off,
+            ]);
+            payloads.push(&list.data);
+        }
+    }
+    if minor >= 2 {
+        for ram in [&fw.iram, &fw.dram] {
+            let ram = ram.as_deref().unwrap_or_default();
+            let off = place(ram);
+            fields.extend([len32(ram), off]);
+            payloads.push(ram);
+        }
+    }
+    build((2, minor), info, &fields, &fw.ucode, &payloads)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(data: &[u8]) -> Vec<u32> {
+        data.chunks_exact(4)
+            .map(|v| u32::from_le_bytes(v.try_into().unwrap()))
+            .collect()
+    }
+
+    const INFO: UcodeInfo = UcodeInfo {
+        ip_version: (9, 0),
+        ucode_version: 0x1B5,
+        feature_version: 47,
+        jt_offset: 2,
+        jt_size: 1,
+    };
+
+    #[test]
+    fn gfx_header() {
+        let ucode = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12];
+        let ret = gfx_v1_0(&ucode, &INFO);
+        assert_eq!(ret.len(), 0x2C + ucode.len());
+        assert_eq!(
+            words(&ret[..0x2C]),
+            [
+                // size_bytes, header_size_bytes
+                0x38,
+                0x2C,
+                // header_version 1.0, ip_version 9.0
+                0x0000_0001,
+                0x0000_0009,
+                // ucode_version, ucode_size_bytes, ucode_array_offset_bytes, crc32
+                0x1B5,
+                12,
+                0x2C,
+                crc32fast::hash(&ucode),
+                // ucode_feature_version, jt_offset, jt_size
+                47,
+                2,
+                1,
+            ]
+        );
+        assert_eq!(ret[0x2C..], ucode);
+    }
+
+    #[test]
+    fn sdma_header() {
+        let ucode = [0xAA; 8];
+        let ret = sdma_v1_0(&ucode, &INFO);
+        let header = words(&ret[..0x30]);
+        assert_eq!(header[..2], [0x38, 0x30]);
+        // ucode_feature_version, ucode_change_version, jt_offset, jt_size
+        assert_eq!(header[8..], [47, 0, 2, 1]);
+        assert_eq!(ret[0x30..], ucode);
+    }
+
+    #[test]
+    fn rlc_header() {
+        let fw = RlcFirmware {
+            ucode: vec![0x11; 8],
+            save_and_restore_offset: 0x100,
+            clear_state_descriptor_offset: 0x200,
+            reg_list_format_start: 0x300,
+            starting_offsets_start: 0x400,
+            reg_list_format_direct_reg_list_length: 0x500,
+            reg_list_format: vec![0x22; 4],
+            reg_list: vec![0x33; 8],
+            gpm: Some(RlcList {
+                ucode_version: 7,
+                feature_version: 3,
+                data: vec![0x44; 4],
+            }),
+            ..Default::default()
+        };
+        let ret = rlc_v2(&fw, &INFO);
+        let header_size = 0x20 + 4 * (19 + 13);
+        let header = words(&ret[..header_size]);
+        assert_eq!(header[1], header_size as u32);
+        // v2.1, as there is a save/restore list.
+        assert_eq!(header[2], 0x0001_0002);
+        assert_eq!(header[8..11], [47, 2, 1]);
+        assert_eq!(header[11..20], [0x100, 0x200, 0, 0, 0, 0x300, 0, 0x400, 4]);
+        let payload = (header_size + 8) as u32;
+        // reg_list_format, then reg_list, then the empty separate lists.
+        assert_eq!(
+            header[20..27],
+            [payload, 8, payload + 4, 0, payload + 12, 0, payload + 12]
+        );
+        assert_eq!(header[27], 0x500);
+        // cntl is absent, gpm follows the lists.
+        assert_eq!(header[28..32], [0, 0, 0, payload + 12]);
+        assert_eq!(header[32..36], [7, 3, 4, payload + 12]);
+        assert_eq!(ret.len(), header_size + 8 + 4 + 8 + 4);
+        assert_eq!(ret[payload as usize + 12..], [0x44; 4]);
+    }
+
+    #[test]
+    fn header_round_trip() {
+        let ret = read_header(&gfx_v1_0(&[0; 16], &INFO)).unwrap();
+        assert_eq!(ret.ip_version, (9, 0));
+        assert_eq!(ret.ucode_version, 0x1B5);
+        assert_eq!(ret.feature_version, 47);
+        assert!(read_header(&[0; 16]).is_none());
+        // Raw ucode whose first dword happens to equal its length is not a header.
+        let mut raw = vec![0; 0x40];
+        raw[0] = 0x40;
+        assert!(read_header(&raw).is_none());
+    }
+}
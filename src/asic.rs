@@ -0,0 +1,227 @@
+//! Copyright © 2024 ChefKiss Inc. Licensed under the Thou Shalt Not Profit License version 1.5.
+//! See LICENSE for details.
+
+use std::fmt;
+
+use crate::firmware::FirmwareType;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Family {
+    /// Southern Islands, GFX6.
+    Si,
+    /// Sea Islands, GFX7.
+    Cik,
+    /// Volcanic Islands, GFX8.
+    Vi,
+    /// Arctic Islands, GFX9.
+    Ai,
+    /// Navi, GFX10.
+    Nv,
+}
+
+impl Family {
+    /// The kernel firmware directory; SI and CIK parts are still named after the radeon driver.
+    pub fn firmware_dir(self) -> &'static str {
+        match self {
+            Self::Si | Self::Cik => "radeon",
+            Self::Vi | Self::Ai | Self::Nv => "amdgpu",
+        }
+    }
+}
+
+impl fmt::Display for Family {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Si => "SI",
+            Self::Cik => "CIK",
+            Self::Vi => "VI",
+            Self::Ai => "AI",
+            Self::Nv => "NV",
+        })
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Asic {
+    /// Name used in kernel firmware file names.
+    pub name: &'static str,
+    /// Code names the Catalyst drivers use instead.
+    pub aliases: &'static [&'static str],
+    pub family: Family,
+    /// GC IP version, as written to amdgpu headers.
+    pub gc_version: (u16, u16),
+}
+
+const fn asic(
+    name: &'static str,
+    aliases: &'static [&'static str],
+    family: Family,
+    gc_version: (u16, u16),
+) -> Asic {
+    Asic {
+        name,
+        aliases,
+        family,
+        gc_version,
+    }
+}
+
+pub static ASICS: &[Asic] = &[
+    asic("tahiti", &[], Family::Si, (6, 0)),
+    asic("pitcairn", &[], Family::Si, (6, 0)),
+    asic("verde", &["capeverde"], Family::Si, (6, 0)),
+    asic("oland", &[], Family::Si, (6, 0)),
+    asic("hainan", &[], Family::Si, (6, 0)),
+    asic("bonaire", &[], Family::Cik, (7, 2)),
+    asic("hawaii", &[], Family::Cik, (7, 3)),
+    asic("kaveri", &["spectre", "spooky"], Family::Cik, (7, 1)),
+    asic("kabini", &["kalindi"], Family::Cik, (7, 2)),
+    asic("mullins", &["godavari"], Family::Cik, (7, 2)),
+    asic("topaz", &["iceland"], Family::Vi, (8, 0)),
+    asic("tonga", &[], Family::Vi, (8, 0)),
+    asic("fiji", &[], Family::Vi, (8, 0)),
+    asic("carrizo", &[], Family::Vi, (8, 0)),
+    asic("stoney", &[], Family::Vi, (8, 1)),
+    asic("polaris10", &["ellesmere"], Family::Vi, (8, 0)),
+    asic("polaris11", &["baffin"], Family::Vi, (8, 0)),
+    asic("polaris12", &["lexa"], Family::Vi, (8, 0)),
+    asic("vegam", &[], Family::Vi, (8, 0)),
+    asic("vega10", &["greenland"], Family::Ai, (9, 0)),
+    asic("vega12", &[], Family::Ai, (9, 2)),
+    asic("vega20", &[], Family::Ai, (9, 4)),
+    asic("raven", &[], Family::Ai, (9, 1)),
+    asic("picasso", &[], Family::Ai, (9, 1)),
+    asic("raven2", &[], Family::Ai, (9, 2)),
+    asic("renoir", &[], Family::Ai, (9, 3)),
+    asic("arcturus", &[], Family::Ai, (9, 4)),
+    asic("navi10", &[], Family::Nv, (10, 1)),
+    asic("navi14", &[], Family::Nv, (10, 1)),
+    asic("navi12", &[], Family::Nv, (10, 1)),
+    asic("sienna_cichlid", &["navi21"], Family::Nv, (10, 3)),
+    asic("navy_flounder", &["navi22"], Family::Nv, (10, 3)),
+    asic("dimgrey_cavefish", &["navi23"], Family::Nv, (10, 3)),
+    asic("beige_goby", &["navi24"], Family::Nv, (10, 3)),
+];
+
+/// IP block names as used in kernel firmware file names.
+const BLOCKS: [&str; 8] = ["pfp", "me", "ce", "mec", "mec2", "rlc", "sdma", "sdma1"];
+
+/// Lowercases `name` and wraps it in `_`, so whole words can be searched for as `_word_`.
+fn words(name: &str) -> String {
+    format!("_{}_", name.to_ascii_lowercase())
+}
+
+impl Asic {
+    /// Finds the ASIC named, or aliased, by a whole word of `name`.
+    pub fn find(name: &str) -> Option<&'static Self> {
+        let name = words(name);
+        ASICS.iter().find(|v| {
+            std::iter::once(&v.name)
+                .chain(v.aliases)
+                .any(|v| name.contains(&format!("_{v}_")))
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Classification {
+    pub asic: &'static Asic,
+    pub block: String,
+}
+
+impl Classification {
+    /// The path the kernel loads this firmware from, e.g. `amdgpu/polaris10_mec.bin`.
+    pub fn file_name(&self) -> String {
+        format!(
+            "{}/{}_{}.bin",
+            self.asic.family.firmware_dir(),
+            self.asic.name,
+            self.block
+        )
+    }
+}
+
+/// Works out the ASIC and IP block of a blob from its symbol name. Symbols without an ASIC, like `_ce_ucode`, are
+/// attributed to `context`, typically the ASIC of the neighbouring descriptors.
+pub fn classify(
+    symbol: &str,
+    ty: FirmwareType,
+    layout: &str,
+    context: Option<&'static Asic>,
+) -> Option<Classification> {
+    let asic = Asic::find(symbol).or(context)?;
+    let name = words(symbol);
+    let block = BLOCKS
+        .iter()
+        .find(|v| name.contains(&format!("_{v}_")))
+        .map(|v| (*v).to_owned())
+        .or_else(|| match ty {
+            FirmwareType::Sdma => Some("sdma".to_owned()),
+            FirmwareType::Rlc => Some("rlc".to_owned()),
+            FirmwareType::Gc if layout.eq_ignore_ascii_case("mec") => Some("mec".to_owned()),
+            FirmwareType::Gc => None,
+        })?;
+    Some(Classification { asic, block })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_whole_words() {
+        let name = |symbol| Asic::find(symbol).map(|v| v.name);
+        assert_eq!(name("_Hawaii_mec"), Some("hawaii"));
+        assert_eq!(name("_Spectre_pfp"), Some("kaveri"));
+        assert_eq!(name("_sienna_cichlid_rlc"), Some("sienna_cichlid"));
+        // `raven` is not a word of `raven2`.
+        assert_eq!(name("_Raven2_me"), Some("raven2"));
+        assert_eq!(name("_Hawaiian_me"), None);
+        assert_eq!(name("_ce_ucode"), None);
+    }
+
+    #[test]
+    fn classifies() {
+        let class = |symbol, ty, layout, context| {
+            classify(symbol, ty, layout, context)
+                .map(|v| (v.asic.name, v.block.clone(), v.file_name()))
+        };
+        assert_eq!(
+            class("_Hawaii_mec2", FirmwareType::Gc, "GC", None),
+            Some((
+                "hawaii",
+                "mec2".to_owned(),
+                "radeon/hawaii_mec2.bin".to_owned()
+            ))
+        );
+        assert_eq!(
+            class("_Ellesmere_sdma1", FirmwareType::Sdma, "SDMA", None),
+            Some((
+                "polaris10",
+                "sdma1".to_owned(),
+                "amdgpu/polaris10_sdma1.bin".to_owned()
+            ))
+        );
+        // Without a block in the name, the type or layout names it, except for GC, which has several.
+        assert_eq!(
+            class("_Tonga_ucode", FirmwareType::Rlc, "RLC", None).map(|v| v.1),
+            Some("rlc".to_owned())
+        );
+        assert_eq!(
+            class("_Tonga_ucode", FirmwareType::Gc, "MEC", None).map(|v| v.1),
+            Some("mec".to_owned())
+        );
+        assert_eq!(class("_Tonga_ucode", FirmwareType::Gc, "GC", None), None);
+        // Without an ASIC in the name, the context's is used.
+        let hawaii = Asic::find("hawaii");
+        assert_eq!(class("_ce_ucode", FirmwareType::Gc, "GC", None), None);
+        assert_eq!(
+            class("_ce_ucode", FirmwareType::Gc, "GC", hawaii).map(|v| v.0),
+            Some("hawaii")
+        );
+        assert_eq!(
+            class("_Tonga_ce", FirmwareType::Gc, "GC", hawaii).map(|v| v.0),
+            Some("tonga")
+        );
+    }
+}
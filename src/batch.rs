@@ -0,0 +1,245 @@
+//! Copyright © 2024 ChefKiss Inc. Licensed under the Thou Shalt Not Profit License version 1.5.
+//! See LICENSE for details.
+
+use std::{
+    collections::HashSet,
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    error::Result,
+    firmware::{Extractor, Firmware, OutputFormat},
+    layout::Layout,
+    manifest::{Manifest, MANIFEST_FILE_NAME},
+    scan,
+    source::{ByteSource, SymbolSource},
+};
+
+/// Extracts every discovered descriptor, dropping candidates whose descriptor overlaps a better scoring one. Blobs
+/// shared by several descriptors, like `_Hawaii_mec` and `_Hawaii_mec2`, are extracted for each of them.
+pub fn collect<S: ByteSource + SymbolSource + ?Sized>(
+    src: &S,
+    layouts: &[Layout],
+    min_score: u32,
+) -> Vec<Firmware> {
+    let pointer_width = src.pointer_width();
+    let mut taken: Vec<Range<u64>> = Vec::new();
+    let mut ret = Vec::new();
+    for candidate in scan::scan(src, layouts, min_score) {
+        let range =
+            candidate.address..candidate.address + candidate.layout.descriptor_size(pointer_width);
+        if taken
+            .iter()
+            .any(|v| v.start < range.end && range.start < v.end)
+        {
+            continue;
+        }
+        let Ok(fw) = Extractor::new(candidate.layout.clone()).read_fw(src, candidate.address)
+        else {
+            continue;
+        };
+        taken.push(range);
+        ret.push(fw);
+    }
+    ret.sort_by_key(|v| v.address);
+    // Descriptors of one ASIC sit together, so unnamed ones like `_ce_ucode` take after the previous one.
+    let mut context = None;
+    for fw in &mut ret {
+        if fw.class.is_none() {
+            fw.classify(context);
+        }
+        context = fw.class.as_ref().map(|v| v.asic).or(context);
+    }
+    ret
+}
+
+/// Writes every blob as `<name>.bin` into `dir`, or under its kernel firmware path with amdgpu output, disambiguating
+/// duplicate names by descriptor address, followed by `manifest`, describing the driver, with where each one came from.
+pub fn write_all(
+    blobs: &[Firmware],
+    dir: &Path,
+    format: OutputFormat,
+    mut manifest: Manifest,
+) -> Result<Vec<PathBuf>> {
+    // Fail before writing anything rather than leave a partial export behind.
+    for blob in blobs {
+        format.check(blob)?;
+    }
+    let mut names = HashSet::new();
+    let mut ret = Vec::with_capacity(blobs.len() + 1);
+    for blob in blobs {
+        let file_name = format.file_name(blob);
+        let path = dir.join(&file_name);
+        let path = if names.insert(file_name) {
+            path
+        } else {
+            path.with_file_name(format!(
+                "{}_{:X}.bin",
+                path.file_stem().unwrap_or_default().to_string_lossy(),
+                blob.address
+            ))
+        };
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let paths = format.write(blob, &path)?;
+        manifest.push(blob, &paths, dir);
+        ret.extend(paths);
+    }
+    let path = dir.join(MANIFEST_FILE_NAME);
+    manifest.write(&path)?;
+    ret.push(path);
+    Ok(ret)
+}
+
+/// One line per blob with its ASIC, block and versions, for comparing against linux-firmware.
+pub fn report(blobs: &[Firmware]) -> String {
+    let mut ret = String::new();
+    for v in blobs {
+        let class = v
+            .class
+            .as_ref()
+            .map(|v| format!("{}/{}", v.asic.name, v.block))
+            .unwrap_or_else(|| "-".to_owned());
+        ret += &format!(
+            "{:#010X} {:<8} {:<24} {:<20} {:#8X} bytes, ucode version {:#x}, feature version {}\n",
+            v.address,
+            v.layout,
+            v.name,
+            class,
+            v.data.len(),
+            v.info.ucode_version,
+            v.info.feature_version
+        );
+    }
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{fixtures, image::Image, source::Endianness};
+
+    const BASE: u64 = 0x10000;
+
+    /// GC descriptors 0x40 bytes apart, each with its symbol and pointing to 0x400 bytes at the given offset.
+    fn image(descriptors: &[(&str, usize)]) -> Image {
+        let mut data = vec![0; 0x2000];
+        for (i, v) in data[0x1000..].iter_mut().enumerate() {
+            *v = (i * 7) as u8;
+        }
+        let mut symbols = Vec::new();
+        for (i, &(name, blob)) in descriptors.iter().enumerate() {
+            let at = 0x40 * i;
+            data[at + 0xC..at + 0x10].copy_from_slice(&0x400u32.to_le_bytes());
+            data[at + 0x20..at + 0x28].copy_from_slice(&(BASE + blob as u64).to_le_bytes());
+            symbols.push((BASE + at as u64, name));
+        }
+        let mut ret = Image::flat(BASE, data, 8, Endianness::Little);
+        for (address, name) in symbols {
+            ret.add_symbol(address, name);
+        }
+        ret
+    }
+
+    #[test]
+    fn keeps_shared_blobs() {
+        let image = image(&[
+            ("_Hawaii_pfp", 0x1000),
+            ("_Hawaii_mec", 0x1400),
+            ("_Hawaii_mec2", 0x1400),
+        ]);
+        let found = collect(&image, &Layout::builtin(), 4);
+        let found: Vec<_> = found
+            .iter()
+            .map(|v| (v.address, v.name.as_str(), v.fw_off))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (BASE, "Hawaii_pfp", BASE + 0x1000),
+                (BASE + 0x40, "Hawaii_mec", BASE + 0x1400),
+                (BASE + 0x80, "Hawaii_mec2", BASE + 0x1400),
+            ]
+        );
+    }
+
+    #[test]
+    fn classifies_by_neighbours() {
+        // `_ce_ucode` takes after the descriptor before it, and the first one has nothing to go by.
+        let image = image(&[
+            ("_me_ucode", 0x1000),
+            ("_Hawaii_pfp", 0x1400),
+            ("_ce_ucode", 0x1800),
+        ]);
+        let found = collect(&image, &Layout::builtin(), 4);
+        let classes: Vec<_> = found
+            .iter()
+            .map(|v| v.class.as_ref().map(|v| v.file_name()))
+            .collect();
+        assert_eq!(
+            classes,
+            [
+                None,
+                Some("radeon/hawaii_pfp.bin".to_owned()),
+                Some("radeon/hawaii_ce.bin".to_owned()),
+            ]
+        );
+        assert_eq!(found[2].info.ip_version, (7, 3));
+    }
+
+    #[test]
+    fn drops_overlapping_descriptors() {
+        // Every built-in layout but SDMA reads the same fields, and so matches each descriptor. Only the best scoring
+        // one, MEC for its name, is extracted.
+        let image = image(&[("_Hawaii_mec", 0x1000)]);
+        let found = collect(&image, &Layout::builtin(), 0);
+        assert_eq!(found.len(), 1);
+        assert_eq!((found[0].address, found[0].layout.as_str()), (BASE, "MEC"));
+    }
+
+    #[test]
+    fn writes_every_blob() {
+        let dir = fixtures::temp_dir("batch");
+        let blobs = [
+            fixtures::firmware("gfx_pfp_ucode", 0x1000, vec![1; 0x10]),
+            fixtures::firmware("gfx_pfp_ucode", 0x2000, vec![2; 0x10]),
+            fixtures::firmware("gfx_me_ucode", 0x3000, vec![3; 0x10]),
+        ];
+        let manifest = Manifest::with_data("driver.sys", b"driver");
+        let paths = write_all(&blobs, &dir, OutputFormat::Raw, manifest).unwrap();
+        let names: Vec<_> = paths
+            .iter()
+            .map(|v| v.strip_prefix(&dir).unwrap().to_string_lossy().into_owned())
+            .collect();
+        // The second `gfx_pfp_ucode` is told apart by its descriptor address.
+        assert_eq!(
+            names,
+            [
+                "gfx_pfp_ucode.bin",
+                "gfx_pfp_ucode_2000.bin",
+                "gfx_me_ucode.bin",
+                MANIFEST_FILE_NAME
+            ]
+        );
+        for (path, blob) in paths.iter().zip(&blobs) {
+            assert_eq!(std::fs::read(path).unwrap(), blob.data);
+        }
+        let manifest = Manifest::read(&dir.join(MANIFEST_FILE_NAME)).unwrap();
+        let files: Vec<_> = manifest
+            .firmware
+            .iter()
+            .map(|v| (v.descriptor_address, v.files.clone()))
+            .collect();
+        assert_eq!(
+            files,
+            [
+                (0x1000, vec!["gfx_pfp_ucode.bin".to_owned()]),
+                (0x2000, vec!["gfx_pfp_ucode_2000.bin".to_owned()]),
+                (0x3000, vec!["gfx_me_ucode.bin".to_owned()]),
+            ]
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,275 @@
+//! Copyright © 2024 ChefKiss Inc. Licensed under the Thou Shalt Not Profit License version 1.5.
+//! See LICENSE for details.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::{firmware::Firmware, manifest::sha256_hex};
+
+/// How the blob under one key differs between the two drivers.
+#[derive(Debug, Clone)]
+pub enum Change<'a> {
+    Added(&'a Firmware),
+    Removed(&'a Firmware),
+    Changed {
+        old: &'a Firmware,
+        new: &'a Firmware,
+        bytes: ByteDiff,
+    },
+    Identical {
+        old: &'a Firmware,
+        new: &'a Firmware,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByteDiff {
+    pub old_size: usize,
+    pub new_size: usize,
+    /// Differing bytes in the common prefix of both blobs.
+    pub differing_bytes: usize,
+    /// Runs of consecutive differing bytes in the common prefix.
+    pub runs: usize,
+    pub first_difference: Option<usize>,
+}
+
+impl ByteDiff {
+    pub fn new(old: &[u8], new: &[u8]) -> Self {
+        let mut ret = Self {
+            old_size: old.len(),
+            new_size: new.len(),
+            differing_bytes: 0,
+            runs: 0,
+            first_difference: None,
+        };
+        let mut in_run = false;
+        for (i, (a, b)) in old.iter().zip(new).enumerate() {
+            if a == b {
+                in_run = false;
+                continue;
+            }
+            ret.differing_bytes += 1;
+            ret.first_difference.get_or_insert(i);
+            if !in_run {
+                ret.runs += 1;
+                in_run = true;
+            }
+        }
+        if ret.first_difference.is_none() && old.len() != new.len() {
+            ret.first_difference = Some(old.len().min(new.len()));
+        }
+        ret
+    }
+}
+
+/// What blobs are matched by: the kernel firmware name when classified, otherwise the symbol-derived name.
+fn key(fw: &Firmware) -> String {
+    fw.class
+        .as_ref()
+        .map(|v| format!("{}_{}", v.asic.name, v.block))
+        .unwrap_or_else(|| fw.name.clone())
+}
+
+fn group(blobs: &[Firmware]) -> BTreeMap<String, &Firmware> {
+    let mut ret = BTreeMap::new();
+    let mut counts = BTreeMap::<String, usize>::new();
+    for fw in blobs {
+        let key = key(fw);
+        let count = counts.entry(key.clone()).or_default();
+        *count += 1;
+        // Duplicates are paired up in address order.
+        let key = if *count == 1 {
+            key
+        } else {
+            format!("{key}#{count}")
+        };
+        ret.insert(key, fw);
+    }
+    ret
+}
+
+fn same(old: &Firmware, new: &Firmware) -> bool {
+    old.data == new.data && old.parts == new.parts
+}
+
+/// Pairs up the blobs of two drivers by name, sorted by key.
+pub fn diff<'a>(old: &'a [Firmware], new: &'a [Firmware]) -> Vec<(String, Change<'a>)> {
+    let mut old = group(old);
+    let mut ret = Vec::new();
+    for (key, new) in group(new) {
+        let change = match old.remove(&key) {
+            None => Change::Added(new),
+            Some(old) if same(old, new) => Change::Identical { old, new },
+            Some(old) => Change::Changed {
+                old,
+                new,
+                bytes: ByteDiff::new(&old.data, &new.data),
+            },
+        };
+        ret.push((key, change));
+    }
+    ret.extend(old.into_iter().map(|(k, v)| (k, Change::Removed(v))));
+    ret.sort_by(|a, b| a.0.cmp(&b.0));
+    ret
+}
+
+fn version(fw: &Firmware) -> String {
+    format!(
+        "ucode version {:#x}, feature version {}",
+        fw.info.ucode_version, fw.info.feature_version
+    )
+}
+
+pub fn report(changes: &[(String, Change<'_>)]) -> String {
+    let mut ret = String::new();
+    let mut counts = [0; 4];
+    for (key, change) in changes {
+        ret += &match change {
+            Change::Added(v) => {
+                counts[0] += 1;
+                format!("+ {key}: {:#X} bytes, {}\n", v.data.len(), version(v))
+            }
+            Change::Removed(v) => {
+                counts[1] += 1;
+                format!("- {key}: {:#X} bytes, {}\n", v.data.len(), version(v))
+            }
+            Change::Changed { old, new, bytes } => {
+                counts[2] += 1;
+                let mut line = format!(
+                    "~ {key}: {:#X} -> {:#X} bytes, {} differing in {} runs",
+                    bytes.old_size, bytes.new_size, bytes.differing_bytes, bytes.runs
+                );
+                if let Some(offset) = bytes.first_difference {
+                    line += &format!(", first at {offset:#X}");
+                }
+                if old.info.ucode_version != new.info.ucode_version {
+                    line += &format!(
+                        ", ucode version {:#x} -> {:#x}",
+                        old.info.ucode_version, new.info.ucode_version
+                    );
+                }
+                if old.info.feature_version != new.info.feature_version {
+                    line += &format!(
+                        ", feature version {} -> {}",
+                        old.info.feature_version, new.info.feature_version
+                    );
+                }
+                let parts: Vec<_> = old
+                    .parts
+                    .keys()
+                    .chain(new.parts.keys())
+                    .filter(|k| old.parts.get(*k) != new.parts.get(*k))
+                    .collect::<BTreeSet<_>>()
+                    .into_iter()
+                    .map(String::as_str)
+                    .collect();
+                if !parts.is_empty() {
+                    line += &format!(", parts changed: {}", parts.join(", "));
+                }
+                line + "\n"
+            }
+            Change::Identical { new, .. } => {
+                counts[3] += 1;
+                format!("= {key}: {}\n", sha256_hex(&new.data))
+            }
+        };
+    }
+    ret += &format!(
+        "{} added, {} removed, {} changed, {} identical\n",
+        counts[0], counts[1], counts[2], counts[3]
+    );
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::firmware;
+
+    #[test]
+    fn byte_diff() {
+        let diff = ByteDiff::new(&[0, 1, 2, 3, 4, 5], &[0, 9, 9, 3, 9, 5, 6]);
+        assert_eq!(
+            diff,
+            ByteDiff {
+                old_size: 6,
+                new_size: 7,
+                differing_bytes: 3,
+                runs: 2,
+                first_difference: Some(1),
+            }
+        );
+        // A blob that only grew differs where the shorter one ends.
+        let diff = ByteDiff::new(&[1, 2], &[1, 2, 3]);
+        assert_eq!((diff.differing_bytes, diff.first_difference), (0, Some(2)));
+        assert_eq!(ByteDiff::new(&[1, 2], &[1, 2]).first_difference, None);
+    }
+
+    #[test]
+    fn groups_duplicates() {
+        let blobs = [
+            firmware("Hawaii_pfp", 0x1000, vec![1]),
+            firmware("Hawaii_pfp", 0x2000, vec![2]),
+            firmware("data_3000", 0x3000, vec![3]),
+        ];
+        let groups: Vec<_> = group(&blobs)
+            .into_iter()
+            .map(|(k, v)| (k, v.address))
+            .collect();
+        // Classified blobs go by their kernel name, in address order, the rest by symbol.
+        assert_eq!(
+            groups,
+            [
+                ("data_3000".to_owned(), 0x3000),
+                ("hawaii_pfp".to_owned(), 0x1000),
+                ("hawaii_pfp#2".to_owned(), 0x2000),
+            ]
+        );
+    }
+
+    #[test]
+    fn pairs_blobs() {
+        let old = [
+            firmware("Hawaii_pfp", 0x1000, vec![1, 2, 3]),
+            firmware("Hawaii_me", 0x1040, vec![4, 5]),
+            firmware("Hawaii_ce", 0x1080, vec![6]),
+        ];
+        let mut mec = firmware("Hawaii_mec", 0x2000, vec![7]);
+        mec.parts.insert("jt".to_owned(), vec![8]);
+        let new = [
+            firmware("Hawaii_pfp", 0x1000, vec![1, 2, 3]),
+            firmware("Hawaii_me", 0x1040, vec![4, 6]),
+            mec,
+        ];
+        let changes: Vec<_> = diff(&old, &new)
+            .into_iter()
+            .map(|(k, v)| {
+                let v = match v {
+                    Change::Added(v) => format!("+{:#x}", v.address),
+                    Change::Removed(v) => format!("-{:#x}", v.address),
+                    Change::Changed { bytes, .. } => format!("~{}", bytes.differing_bytes),
+                    Change::Identical { .. } => "=".to_owned(),
+                };
+                (k, v)
+            })
+            .collect();
+        assert_eq!(
+            changes,
+            [
+                ("hawaii_ce".to_owned(), "-0x1080".to_owned()),
+                ("hawaii_me".to_owned(), "~1".to_owned()),
+                ("hawaii_mec".to_owned(), "+0x2000".to_owned()),
+                ("hawaii_pfp".to_owned(), "=".to_owned()),
+            ]
+        );
+
+        // Blobs with the same ucode differ by their parts.
+        let mut changed = new[2].clone();
+        changed.parts.insert("jt".to_owned(), vec![9]);
+        let changes = diff(&new[2..], std::slice::from_ref(&changed));
+        assert!(matches!(
+            changes[0].1,
+            Change::Changed { bytes, .. } if bytes.differing_bytes == 0
+        ));
+        assert!(report(&changes).contains("parts changed: jt"));
+    }
+}
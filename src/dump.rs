@@ -0,0 +1,85 @@
+//! Copyright © 2024 ChefKiss Inc. Licensed under the Thou Shalt Not Profit License version 1.5.
+//! See LICENSE for details.
+
+use std::{fmt::Write, ops::Range};
+
+use crate::firmware::Firmware;
+
+/// Lists the ucode in `data` dword by dword, with the jump table occupying the dwords in `jt` listed by index, and any
+/// bytes past the last whole dword at the end. The instruction encodings and what the jump table is indexed by are
+/// undocumented, so neither is decoded.
+pub fn listing(data: &[u8], jt: Option<Range<usize>>) -> String {
+    let words = data.chunks_exact(4);
+    let tail = words.remainder();
+    let words: Vec<_> = words
+        .map(|v| u32::from_le_bytes(v.try_into().unwrap()))
+        .collect();
+    let jt = jt
+        .map(|v| v.start.min(words.len())..v.end.min(words.len()))
+        .unwrap_or_default();
+
+    let mut ret = String::new();
+    if !jt.is_empty() {
+        writeln!(ret, "; jump table at {:#x}, {} entries", jt.start, jt.len()).unwrap();
+    }
+    for (i, &word) in words.iter().enumerate() {
+        if i == jt.start && !jt.is_empty() {
+            ret += "\njump_table:\n";
+        }
+        if i == jt.end && !jt.is_empty() {
+            ret.push('\n');
+        }
+        let text = if jt.contains(&i) {
+            format!(".entry   {:#04x}", i - jt.start)
+        } else {
+            ".word".to_owned()
+        };
+        writeln!(ret, "    {i:#06x}: {word:08x}  {text}").unwrap();
+    }
+    if !tail.is_empty() {
+        let bytes: String = tail.iter().map(|v| format!("{v:02x}")).collect();
+        writeln!(ret, "    {:#06x}: {bytes:<8}  .byte", words.len()).unwrap();
+    }
+    ret
+}
+
+/// Lists `fw`, with its jump table if the layout locates one.
+pub fn firmware(fw: &Firmware) -> String {
+    let (data, info) = fw.with_jump_table();
+    let start = info.jt_offset as usize;
+    let jt = (info.jt_size != 0).then(|| start..start + info.jt_size as usize);
+    listing(&data, jt)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lists_words_jump_table_and_tail() {
+        let data: Vec<u8> = [0xDEAD_BEEFu32, 0x1234_5678, 0x10, 0x20, 0xC0FF_EE00]
+            .iter()
+            .flat_map(|v| v.to_le_bytes())
+            .chain([0xAA, 0xBB])
+            .collect();
+        assert_eq!(
+            listing(&data, Some(2..4)),
+            "; jump table at 0x2, 2 entries
+    0x0000: deadbeef  .word
+    0x0001: 12345678  .word
+
+jump_table:
+    0x0002: 00000010  .entry   0x00
+    0x0003: 00000020  .entry   0x01
+
+    0x0004: c0ffee00  .word
+    0x0005: aabb      .byte
+"
+        );
+        // A jump table past the end is cut short.
+        assert!(listing(&data, Some(4..8)).starts_with("; jump table at 0x4, 1 entries\n"));
+        assert_eq!(listing(&data[..8], None).lines().count(), 2);
+        // Bytes short of a dword are kept, in file order.
+        assert_eq!(listing(&data[..3], None), "    0x0000: efbead    .byte\n");
+    }
+}
@@ -0,0 +1,126 @@
+//! Copyright © 2024 ChefKiss Inc. Licensed under the Thou Shalt Not Profit License version 1.5.
+//! See LICENSE for details.
+
+use std::{fmt, io};
+
+#[derive(Debug)]
+pub enum Error {
+    /// The descriptor fields could not be read, e.g. the address is unmapped.
+    UnreadableDescriptor {
+        address: u64,
+    },
+    /// The firmware pointer or its end lies outside of the image.
+    PointerOutOfBounds {
+        address: u64,
+        fw_off: u64,
+        fw_size: u32,
+    },
+    /// The firmware does not fit in the address space.
+    SizeOverflow {
+        fw_off: u64,
+        fw_size: u32,
+    },
+    /// No layout yields a valid descriptor at the address.
+    NoMatchingLayout {
+        address: u64,
+    },
+    /// The pointer field is not 4 or 8 bytes wide.
+    AddressWidth(usize),
+    /// The address is not backed by the driver file, so it can't be patched.
+    NotFileBacked {
+        address: u64,
+    },
+    /// A replacement blob is larger than the original, and the driver can't take a new section for it.
+    BlobTooLarge {
+        address: u64,
+        size: usize,
+        capacity: u32,
+    },
+    /// The PE headers have no room for another section header.
+    NoRoomForSection,
+    /// The layout lacks `values` the output format needs, e.g. the RLC header offsets.
+    MissingValues {
+        address: u64,
+        names: Vec<String>,
+    },
+    /// A store lookup was given something other than a SHA-256 or a long enough prefix of one.
+    InvalidHashPrefix(String),
+    /// The `LC_DYLD_CHAINED_FIXUPS` of a Mach-O can't be followed, so its pointers can't be rebased.
+    MalformedChainedFixups,
+    /// The PE headers or base relocations can't be parsed, so the driver can't be mapped at the requested base.
+    MalformedPe(object::Error),
+    Io(io::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnreadableDescriptor { address } => {
+                write!(f, "The descriptor at {address:#X} could not be read")
+            }
+            Self::PointerOutOfBounds {
+                address,
+                fw_off,
+                fw_size,
+            } => write!(
+                f,
+                "The descriptor at {address:#X} points to {fw_size:#X} bytes at {fw_off:#X}, which is outside of the image"
+            ),
+            Self::SizeOverflow { fw_off, fw_size } => {
+                write!(f, "{fw_size:#X} bytes at {fw_off:#X} overflow the address space")
+            }
+            Self::NoMatchingLayout { address } => {
+                write!(f, "No descriptor layout matches the data at {address:#X}")
+            }
+            Self::AddressWidth(width) => {
+                write!(f, "Unsupported pointer width of {width} bytes")
+            }
+            Self::NotFileBacked { address } => {
+                write!(f, "{address:#X} is not backed by the driver file")
+            }
+            Self::BlobTooLarge {
+                address,
+                size,
+                capacity,
+            } => write!(
+                f,
+                "{size:#X} bytes don't fit in the {capacity:#X} the descriptor at {address:#X} points to, and only PE drivers can be given a new section"
+            ),
+            Self::NoRoomForSection => write!(f, "The PE headers have no room for another section"),
+            Self::MissingValues { address, names } => write!(
+                f,
+                "The layout of the descriptor at {address:#X} has no {} values, which the amdgpu header needs",
+                names.join(", ")
+            ),
+            Self::InvalidHashPrefix(hash) => write!(
+                f,
+                "`{hash}` is neither a SHA-256 nor a prefix of at least {} hex digits of one",
+                crate::store::MIN_HASH_PREFIX
+            ),
+            Self::MalformedChainedFixups => write!(
+                f,
+                "The chained fixups of the Mach-O are malformed, so its pointers can't be rebased"
+            ),
+            Self::MalformedPe(e) => write!(f, "The PE driver is malformed: {e}"),
+            Self::Io(e) => write!(f, "File access failed: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::MalformedPe(e) => Some(e),
+            Self::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(value: io::Error) -> Self {
+        Self::Io(value)
+    }
+}
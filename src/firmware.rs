@@ -0,0 +1,746 @@
+//! Copyright © 2024 ChefKiss Inc. Licensed under the Thou Shalt Not Profit License version 1.5.
+//! See LICENSE for details.
+
+use std::{
+    collections::BTreeMap,
+    fmt,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    amdgpu::{self, RlcFirmware, RlcList, UcodeInfo},
+    asic::{self, Asic, Classification},
+    error::Error,
+    layout::{Field, Layout},
+    source::{ByteSource, SymbolSource},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FirmwareType {
+    Gc,
+    Sdma,
+    Rlc,
+}
+
+impl FirmwareType {
+    pub const ALL: [Self; 3] = [Self::Gc, Self::Sdma, Self::Rlc];
+}
+
+impl fmt::Display for FirmwareType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Gc => "gc",
+            Self::Sdma => "sdma",
+            Self::Rlc => "rlc",
+        })
+    }
+}
+
+impl FromStr for FirmwareType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "gc" => Ok(Self::Gc),
+            "sdma" => Ok(Self::Sdma),
+            "rlc" => Ok(Self::Rlc),
+            _ => Err(format!("unknown firmware type `{s}`")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputFormat {
+    /// The ucode bytes as found in the driver.
+    #[default]
+    Raw,
+    /// Wrapped in the amdgpu firmware header for the IP block.
+    Amdgpu,
+}
+
+impl OutputFormat {
+    /// Checks that `fw` carries everything this format needs, i.e. the header values for amdgpu RLC output.
+    pub fn check(self, fw: &Firmware) -> Result<(), Error> {
+        if (self, fw.ty) != (Self::Amdgpu, FirmwareType::Rlc) {
+            return Ok(());
+        }
+        let missing = fw.missing_rlc_values();
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::MissingValues {
+                address: fw.address,
+                names: missing,
+            })
+        }
+    }
+
+    /// The files to write for `fw`, as file name suffixes and contents. The main blob has an empty suffix.
+    pub fn encode(self, fw: &Firmware) -> Result<Vec<(String, Vec<u8>)>, Error> {
+        self.check(fw)?;
+        let (main, consumed): (_, &[&str]) = match (self, fw.ty) {
+            (Self::Raw, _) => (fw.data.clone(), &[]),
+            (Self::Amdgpu, FirmwareType::Gc) => {
+                let (data, info) = fw.with_jump_table();
+                (amdgpu::gfx_v1_0(&data, &info), &["jt"])
+            }
+            (Self::Amdgpu, FirmwareType::Sdma) => {
+                let (data, info) = fw.with_jump_table();
+                (amdgpu::sdma_v1_0(&data, &info), &["jt"])
+            }
+            (Self::Amdgpu, FirmwareType::Rlc) => {
+                (amdgpu::rlc_v2(&fw.rlc(), &fw.info), &RLC_HEADER_PARTS)
+            }
+        };
+        let mut ret = vec![(String::new(), main)];
+        ret.extend(
+            fw.parts
+                .iter()
+                .filter(|(k, _)| !consumed.contains(&k.as_str()))
+                .map(|(k, v)| (format!("_{k}"), v.clone())),
+        );
+        if self == Self::Raw && !fw.parts.contains_key("jt") {
+            if let Some(jt) = fw.embedded_jump_table() {
+                ret.push(("_jt".to_owned(), jt.to_vec()));
+            }
+        }
+        Ok(ret)
+    }
+
+    /// Default file name for `fw`: its kernel firmware path with amdgpu headers, otherwise `<name>.bin` tagged with the
+    /// known versions.
+    pub fn file_name(self, fw: &Firmware) -> String {
+        match self {
+            Self::Raw => format!("{}{}.bin", fw.name, fw.version_suffix()),
+            Self::Amdgpu => fw.file_name(),
+        }
+    }
+
+    /// Writes the main blob to `path` and any extra files next to it, suffixed. Returns every written path.
+    pub fn write(self, fw: &Firmware, path: &Path) -> Result<Vec<PathBuf>, Error> {
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        let ext = path
+            .extension()
+            .map(|v| format!(".{}", v.to_string_lossy()))
+            .unwrap_or_default();
+        let mut ret = Vec::new();
+        for (suffix, data) in self.encode(fw)? {
+            let path = path.with_file_name(format!("{stem}{suffix}{ext}"));
+            std::fs::write(&path, data)?;
+            ret.push(path);
+        }
+        Ok(ret)
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "raw" => Ok(Self::Raw),
+            "amdgpu" => Ok(Self::Amdgpu),
+            _ => Err(format!("unknown output format `{s}`")),
+        }
+    }
+}
+
+/// Parts `rlc_firmware_header_v2_x` embeds. Anything else, like the clear-state buffer, is written separately.
+const RLC_HEADER_PARTS: [&str; 9] = [
+    "reg_list_format",
+    "reg_list",
+    "reg_list_format_separate",
+    "reg_list_separate",
+    "cntl",
+    "gpm",
+    "srm",
+    "iram",
+    "dram",
+];
+
+/// Scalar fields of `rlc_firmware_header_v2_0`, read from the layout's `values`.
+const RLC_HEADER_VALUES: [&str; 9] = [
+    "save_and_restore_offset",
+    "clear_state_descriptor_offset",
+    "avail_scratch_ram_locations",
+    "master_pkt_description_offset",
+    "reg_restore_list_size",
+    "reg_list_format_start",
+    "reg_list_format_separate_start",
+    "starting_offsets_start",
+    // Only in `v2_1` onwards, i.e. with save/restore lists.
+    "reg_list_format_direct_reg_list_length",
+];
+
+/// Save/restore lists of `rlc_firmware_header_v2_1`, whose versions are read from `<list>_ucode_version` and
+/// `<list>_feature_version` values.
+const RLC_LISTS: [&str; 3] = ["cntl", "gpm", "srm"];
+
+#[derive(Debug, Clone)]
+pub struct Firmware {
+    pub name: String,
+    /// Name of the layout the descriptor was read with.
+    pub layout: String,
+    /// Address of the descriptor the blob was found through.
+    pub address: u64,
+    pub ty: FirmwareType,
+    pub fw_off: u64,
+    /// Where the blob is in the driver file, when the source is backed by one.
+    pub file_offset: Option<u64>,
+    pub data: Vec<u8>,
+    pub info: UcodeInfo,
+    pub parts: BTreeMap<String, Vec<u8>>,
+    pub values: BTreeMap<String, u32>,
+    /// ASIC and IP block, when they could be worked out.
+    pub class: Option<Classification>,
+}
+
+impl Firmware {
+    /// Attributes the blob to an ASIC and IP block, see `asic::classify`. GC and RLC headers get the GC IP version.
+    pub fn classify(&mut self, context: Option<&'static Asic>) {
+        self.class = asic::classify(&self.name, self.ty, &self.layout, context);
+        if let (Some(class), FirmwareType::Gc | FirmwareType::Rlc) = (&self.class, self.ty) {
+            self.info.ip_version = class.asic.gc_version;
+        }
+    }
+
+    /// `_v<ucode version>_f<feature version>`, leaving out unknown (zero) ones.
+    pub fn version_suffix(&self) -> String {
+        let mut ret = String::new();
+        if self.info.ucode_version != 0 {
+            ret += &format!("_v{:#x}", self.info.ucode_version);
+        }
+        if self.info.feature_version != 0 {
+            ret += &format!("_f{}", self.info.feature_version);
+        }
+        ret
+    }
+
+    /// The kernel firmware path if the blob was classified, otherwise `<name>.bin`.
+    pub fn file_name(&self) -> String {
+        self.class
+            .as_ref()
+            .map(Classification::file_name)
+            .unwrap_or_else(|| format!("{}.bin", self.name))
+    }
+
+    /// The jump table inside the ucode, as located by the descriptor's `jt_offset`/`jt_size` fields.
+    pub fn embedded_jump_table(&self) -> Option<&[u8]> {
+        if self.info.jt_size == 0 {
+            return None;
+        }
+        let start = usize::try_from(self.info.jt_offset).ok()?.checked_mul(4)?;
+        let len = usize::try_from(self.info.jt_size).ok()?.checked_mul(4)?;
+        self.data.get(start..start.checked_add(len)?)
+    }
+
+    /// The ucode with a separately stored jump table appended, as amdgpu expects it for MEC. As the header counts in
+    /// dwords, both are zero-padded to whole ones.
+    pub fn with_jump_table(&self) -> (Vec<u8>, UcodeInfo) {
+        let Some(jt) = self.parts.get("jt") else {
+            return (self.data.clone(), self.info);
+        };
+        let mut data = self.data.clone();
+        data.resize(data.len().next_multiple_of(4), 0);
+        let jt_offset = data.len() / 4;
+        data.extend_from_slice(jt);
+        data.resize(data.len().next_multiple_of(4), 0);
+        let info = UcodeInfo {
+            jt_offset: jt_offset as u32,
+            jt_size: (data.len() / 4 - jt_offset) as u32,
+            ..self.info
+        };
+        (data, info)
+    }
+
+    /// The values `rlc_firmware_header_v2_x` needs that the descriptor didn't provide.
+    pub fn missing_rlc_values(&self) -> Vec<String> {
+        let lists: Vec<_> = RLC_LISTS
+            .iter()
+            .filter(|v| self.parts.contains_key(**v))
+            .collect();
+        let header = if lists.is_empty() {
+            &RLC_HEADER_VALUES[..RLC_HEADER_VALUES.len() - 1]
+        } else {
+            &RLC_HEADER_VALUES[..]
+        };
+        header
+            .iter()
+            .map(|v| (*v).to_owned())
+            .chain(
+                lists
+                    .iter()
+                    .flat_map(|v| [format!("{v}_ucode_version"), format!("{v}_feature_version")]),
+            )
+            .filter(|v| !self.values.contains_key(v))
+            .collect()
+    }
+
+    pub fn rlc(&self) -> RlcFirmware {
+        let part = |name: &str| self.parts.get(name).cloned().unwrap_or_default();
+        let value = |name: &str| self.values.get(name).copied().unwrap_or_default();
+        let list = |name: &str| {
+            self.parts.get(name).map(|data| RlcList {
+                ucode_version: value(&format!("{name}_ucode_version")),
+                feature_version: value(&format!("{name}_feature_version")),
+                data: data.clone(),
+            })
+        };
+        RlcFirmware {
+            ucode: self.data.clone(),
+            save_and_restore_offset: value("save_and_restore_offset"),
+            clear_state_descriptor_offset: value("clear_state_descriptor_offset"),
+            avail_scratch_ram_locations: value("avail_scratch_ram_locations"),
+            master_pkt_description_offset: value("master_pkt_description_offset"),
+            reg_restore_list_size: value("reg_restore_list_size"),
+            reg_list_format_start: value("reg_list_format_start"),
+            reg_list_format_separate_start: value("reg_list_format_separate_start"),
+            starting_offsets_start: value("starting_offsets_start"),
+            reg_list_format_direct_reg_list_length: value("reg_list_format_direct_reg_list_length"),
+            reg_list_format: part("reg_list_format"),
+            reg_list: part("reg_list"),
+            reg_list_format_separate: part("reg_list_format_separate"),
+            reg_list_separate: part("reg_list_separate"),
+            cntl: list("cntl"),
+            gpm: list("gpm"),
+            srm: list("srm"),
+            iram: self.parts.get("iram").cloned(),
+            dram: self.parts.get("dram").cloned(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Extractor(Layout);
+
+impl Extractor {
+    pub fn new(layout: Layout) -> Self {
+        Self(layout)
+    }
+
+    pub fn layout(&self) -> &Layout {
+        &self.0
+    }
+
+    pub fn ty(&self) -> FirmwareType {
+        self.0.ty
+    }
+
+    pub fn read_fw_size<S: ByteSource + ?Sized>(&self, src: &S, offset: u64) -> Result<u32, Error> {
+        self.0
+            .size
+            .read(src, offset, 4)
+            .and_then(|v| v.try_into().ok())
+            .ok_or(Error::UnreadableDescriptor { address: offset })
+    }
+
+    pub fn read_fw_off<S: ByteSource + ?Sized>(&self, src: &S, offset: u64) -> Result<u64, Error> {
+        let width = self.0.pointer.width(src.pointer_width());
+        if !matches!(width, 4 | 8) {
+            return Err(Error::AddressWidth(width));
+        }
+        self.0
+            .pointer
+            .read(src, offset, width)
+            .ok_or(Error::UnreadableDescriptor { address: offset })
+    }
+
+    pub fn read_fw_info<S: ByteSource + ?Sized>(
+        &self,
+        src: &S,
+        offset: u64,
+    ) -> Result<(u64, u32), Error> {
+        Ok((
+            self.read_fw_off(src, offset)?,
+            self.read_fw_size(src, offset)?,
+        ))
+    }
+
+    /// Reads the optional version and jump table fields, leaving absent ones zero.
+    pub fn read_ucode_info<S: ByteSource + ?Sized>(&self, src: &S, offset: u64) -> UcodeInfo {
+        let read = |field: &Option<Field>| {
+            field
+                .as_ref()
+                .and_then(|v| v.read(src, offset, 4))
+                .and_then(|v| u32::try_from(v).ok())
+                .unwrap_or_default()
+        };
+        UcodeInfo {
+            ucode_version: read(&self.0.version),
+            feature_version: read(&self.0.feature_version),
+            jt_offset: read(&self.0.jt_offset),
+            jt_size: read(&self.0.jt_size),
+            ..Default::default()
+        }
+    }
+
+    /// Fills in versions the descriptor lacks from the layout's embedded fields, or an amdgpu header on the ucode.
+    pub fn read_embedded_versions<S: ByteSource + ?Sized>(
+        &self,
+        src: &S,
+        fw_off: u64,
+        data: &[u8],
+        info: &mut UcodeInfo,
+    ) {
+        let read = |field: &Option<Field>| {
+            field
+                .as_ref()
+                .and_then(|v| v.read(src, fw_off, 4))
+                .and_then(|v| u32::try_from(v).ok())
+        };
+        let header = amdgpu::read_header(data);
+        if info.ucode_version == 0 {
+            info.ucode_version = read(&self.0.embedded_version)
+                .or(header.map(|v| v.ucode_version))
+                .unwrap_or_default();
+        }
+        if info.feature_version == 0 {
+            info.feature_version = read(&self.0.embedded_feature_version)
+                .or(header.map(|v| v.feature_version))
+                .unwrap_or_default();
+        }
+    }
+
+    /// Reads every secondary blob whose pointer and size are in bounds.
+    pub fn read_parts<S: ByteSource + ?Sized>(
+        &self,
+        src: &S,
+        offset: u64,
+    ) -> BTreeMap<String, Vec<u8>> {
+        let pointer_width = src.pointer_width();
+        self.0
+            .parts
+            .iter()
+            .filter_map(|(name, part)| {
+                let size = usize::try_from(part.size.read(src, offset, 4)?).ok()?;
+                let ptr = part.pointer.read(src, offset, pointer_width)?;
+                if size == 0 || !src.contains(ptr) || !src.contains(ptr + size as u64 - 1) {
+                    return None;
+                }
+                Some((name.clone(), src.read_bytes(ptr, size)))
+            })
+            .collect()
+    }
+
+    pub fn read_values<S: ByteSource + ?Sized>(
+        &self,
+        src: &S,
+        offset: u64,
+    ) -> BTreeMap<String, u32> {
+        self.0
+            .values
+            .iter()
+            .filter_map(|(name, field)| {
+                Some((
+                    name.clone(),
+                    u32::try_from(field.read(src, offset, 4)?).ok()?,
+                ))
+            })
+            .collect()
+    }
+
+    pub fn sym_to_fw_name(name: &str) -> String {
+        name.strip_prefix('_').unwrap_or(name).to_owned()
+    }
+
+    pub fn fw_info_addr<S: SymbolSource + ?Sized>(src: &S, offset: u64) -> u64 {
+        src.symbol_at(offset).map(|v| v.address).unwrap_or(offset)
+    }
+
+    pub fn read_fw_info_of_sym<S: ByteSource + SymbolSource + ?Sized>(
+        &self,
+        src: &S,
+        offset: u64,
+    ) -> Result<(String, u64, u32), Error> {
+        let (fw_name, address) = src
+            .symbol_at(offset)
+            .map(|v| (Self::sym_to_fw_name(&v.name), v.address))
+            .unwrap_or_else(|| (format!("data_{offset:X}"), offset));
+        self.read_fw_info(src, address)
+            .map(|(fw_off, fw_size)| (fw_name, fw_off, fw_size))
+    }
+
+    /// Checks that the descriptor at `offset` reads and points to firmware inside the image.
+    pub fn check_fw<S: ByteSource + SymbolSource + ?Sized>(
+        &self,
+        src: &S,
+        offset: u64,
+    ) -> Result<(), Error> {
+        let address = Self::fw_info_addr(src, offset);
+        let (fw_off, fw_size) = self.read_fw_info(src, address)?;
+        let last = fw_off
+            .checked_add(u64::from(fw_size.max(1)) - 1)
+            .ok_or(Error::SizeOverflow { fw_off, fw_size })?;
+        if !src.contains(fw_off) || !src.contains(last) {
+            return Err(Error::PointerOutOfBounds {
+                address,
+                fw_off,
+                fw_size,
+            });
+        }
+        Ok(())
+    }
+
+    pub fn fw_valid<S: ByteSource + SymbolSource + ?Sized>(&self, src: &S, offset: u64) -> bool {
+        self.check_fw(src, offset).is_ok()
+    }
+
+    pub fn read_fw<S: ByteSource + SymbolSource + ?Sized>(
+        &self,
+        src: &S,
+        offset: u64,
+    ) -> Result<Firmware, Error> {
+        self.check_fw(src, offset)?;
+        let (name, fw_off, fw_size) = self.read_fw_info_of_sym(src, offset)?;
+        let address = Self::fw_info_addr(src, offset);
+        let len = usize::try_from(fw_size).map_err(|_| Error::SizeOverflow { fw_off, fw_size })?;
+        let mut ret = Firmware {
+            name,
+            layout: self.0.name.clone(),
+            address,
+            ty: self.0.ty,
+            fw_off,
+            file_offset: src.file_offset(fw_off),
+            data: src.read_bytes(fw_off, len),
+            info: self.read_ucode_info(src, address),
+            parts: self.read_parts(src, address),
+            values: self.read_values(src, address),
+            class: None,
+        };
+        self.read_embedded_versions(src, fw_off, &ret.data, &mut ret.info);
+        ret.classify(None);
+        Ok(ret)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        fixtures::{self, extractor},
+        image::Image,
+        source::Endianness,
+    };
+
+    const BASE: u64 = 0x1000;
+    const BLOB: u64 = 0x1100;
+
+    /// A little-endian image with a descriptor at `BASE` holding `size` and `pointer` at the given offsets, and a
+    /// recognisable blob at `BLOB`.
+    fn image(
+        size_at: usize,
+        pointer_at: usize,
+        pointer_width: usize,
+        size: u32,
+        pointer: u64,
+    ) -> Image {
+        let mut data = vec![0; 0x200];
+        data[size_at..size_at + 4].copy_from_slice(&size.to_le_bytes());
+        data[pointer_at..pointer_at + pointer_width]
+            .copy_from_slice(&pointer.to_le_bytes()[..pointer_width]);
+        for (i, v) in data[(BLOB - BASE) as usize..].iter_mut().enumerate() {
+            *v = i as u8;
+        }
+        Image::flat(BASE, data, pointer_width, Endianness::Little)
+    }
+
+    #[test]
+    fn gc_descriptor() {
+        let mut image = image(0xC, 0x20, 8, 0x40, BLOB);
+        image.add_symbol(BASE, "_gfx_pfp_ucode");
+        let extractor = extractor("GC");
+        assert_eq!(extractor.read_fw_info(&image, BASE).unwrap(), (BLOB, 0x40));
+        extractor.check_fw(&image, BASE).unwrap();
+
+        let fw = extractor.read_fw(&image, BASE).unwrap();
+        assert_eq!(fw.name, "gfx_pfp_ucode");
+        assert_eq!(fw.ty, FirmwareType::Gc);
+        assert_eq!(fw.address, BASE);
+        assert_eq!(fw.fw_off, BLOB);
+        assert_eq!(fw.file_offset, Some(BLOB - BASE));
+        assert_eq!(fw.data, (0..0x40).collect::<Vec<u8>>());
+    }
+
+    #[test]
+    fn sdma_descriptor() {
+        let image = image(0x8, 0x10, 8, 0x20, BLOB);
+        let extractor = extractor("SDMA");
+        assert_eq!(extractor.read_fw_info(&image, BASE).unwrap(), (BLOB, 0x20));
+
+        let fw = extractor.read_fw(&image, BASE).unwrap();
+        assert_eq!(fw.name, "data_1000");
+        assert_eq!(fw.ty, FirmwareType::Sdma);
+        assert_eq!(fw.data, (0..0x20).collect::<Vec<u8>>());
+    }
+
+    #[test]
+    fn native_pointer_width() {
+        // 32-bit drivers read 4-byte pointers, and the SDMA pointer moves up to follow the size.
+        let sdma = image(0x8, 0xC, 4, 0x20, BLOB);
+        let fw = extractor("SDMA").read_fw(&sdma, BASE).unwrap();
+        assert_eq!(fw.fw_off, BLOB);
+        assert_eq!(fw.data, (0..0x20).collect::<Vec<u8>>());
+
+        let gc = image(0xC, 0x20, 4, 0x40, BLOB);
+        assert_eq!(
+            extractor("GC").read_fw_info(&gc, BASE).unwrap(),
+            (BLOB, 0x40)
+        );
+
+        // Whatever follows a 4-byte pointer must not leak into it.
+        let mut data = vec![0; 0x200];
+        data[0x8..0xC].copy_from_slice(&0x20u32.to_le_bytes());
+        data[0xC..0x10].copy_from_slice(&(BLOB as u32).to_le_bytes());
+        data[0x10..0x14].fill(0xFF);
+        let padded = Image::flat(BASE, data, 4, Endianness::Little);
+        assert_eq!(
+            extractor("SDMA").read_fw_info(&padded, BASE).unwrap(),
+            (BLOB, 0x20)
+        );
+    }
+
+    #[test]
+    fn big_endian() {
+        let mut data = vec![0; 0x200];
+        data[0xC..0x10].copy_from_slice(&0x40u32.to_be_bytes());
+        data[0x20..0x24].copy_from_slice(&(BLOB as u32).to_be_bytes());
+        let image = Image::flat(BASE, data, 4, Endianness::Big);
+        assert_eq!(
+            extractor("GC").read_fw_info(&image, BASE).unwrap(),
+            (BLOB, 0x40)
+        );
+    }
+
+    #[test]
+    fn unsupported_pointer_width() {
+        let image = image(0xC, 0x20, 8, 0x40, BLOB);
+        let mut layout = fixtures::layout("GC");
+        layout.pointer.width = Some(2);
+        assert!(matches!(
+            Extractor::new(layout).read_fw_off(&image, BASE),
+            Err(Error::AddressWidth(2))
+        ));
+    }
+
+    #[test]
+    fn amdgpu_rlc_needs_values() {
+        let rlc = image(0xC, 0x20, 8, 0x40, BLOB);
+        let fw = extractor("RLC").read_fw(&rlc, BASE).unwrap();
+        assert!(OutputFormat::Raw.encode(&fw).is_ok());
+        assert!(matches!(
+            OutputFormat::Amdgpu.encode(&fw),
+            Err(Error::MissingValues { address: BASE, names }) if names.len() == 8
+        ));
+
+        let mut layout = fixtures::layout("RLC");
+        let mut data = rlc.read_bytes(BASE, 0x200);
+        for (i, name) in RLC_HEADER_VALUES[..8].iter().enumerate() {
+            let offset = 0xA0 + 4 * i;
+            layout
+                .values
+                .insert((*name).to_owned(), Field::new(offset as u64));
+            data[offset..offset + 4].copy_from_slice(&(i as u32 + 1).to_le_bytes());
+        }
+        let rlc = Image::flat(BASE, data, 8, Endianness::Little);
+        let fw = Extractor::new(layout).read_fw(&rlc, BASE).unwrap();
+        let files = OutputFormat::Amdgpu.encode(&fw).unwrap();
+        // The values follow the common header, the feature version and the jump table fields.
+        let values: Vec<_> = files[0].1[0x2C..0x4C]
+            .chunks_exact(4)
+            .map(|v| u32::from_le_bytes(v.try_into().unwrap()))
+            .collect();
+        assert_eq!(values, (1..=8).collect::<Vec<u32>>());
+    }
+
+    #[test]
+    fn pointer_out_of_bounds() {
+        let extractor = extractor("GC");
+        for (pointer, size) in [(0x8000, 0x40), (BLOB, 0x200)] {
+            let image = image(0xC, 0x20, 8, size, pointer);
+            assert!(matches!(
+                extractor.check_fw(&image, BASE),
+                Err(Error::PointerOutOfBounds { address: BASE, fw_off, fw_size })
+                    if fw_off == pointer && fw_size == size
+            ));
+            assert!(!extractor.fw_valid(&image, BASE));
+            assert!(extractor.read_fw(&image, BASE).is_err());
+        }
+    }
+
+    #[test]
+    fn size_overflow() {
+        let image = image(0xC, 0x20, 8, 0x40, u64::MAX - 0x10);
+        assert!(matches!(
+            extractor("GC").check_fw(&image, BASE),
+            Err(Error::SizeOverflow { .. })
+        ));
+    }
+
+    #[test]
+    fn unreadable_descriptor() {
+        let image = image(0xC, 0x20, 8, 0x40, BLOB);
+        assert!(matches!(
+            extractor("GC").read_fw_info(&image, 0x8000),
+            Err(Error::UnreadableDescriptor { address: 0x8000 })
+        ));
+    }
+
+    #[test]
+    fn jump_table() {
+        let mut fw = fixtures::firmware("Hawaii_mec", BASE, (0..0x20).collect());
+        assert_eq!(fw.with_jump_table(), (fw.data.clone(), fw.info));
+        assert_eq!(fw.embedded_jump_table(), None);
+
+        // Located by the descriptor, inside the ucode.
+        fw.info.jt_offset = 4;
+        fw.info.jt_size = 2;
+        assert_eq!(fw.embedded_jump_table(), Some(&fw.data[0x10..0x18]));
+        fw.info.jt_size = 5;
+        assert_eq!(fw.embedded_jump_table(), None);
+        fw.info = UcodeInfo::default();
+
+        // Stored apart, and appended after padding both to dwords.
+        fw.data.truncate(0x1E);
+        fw.parts.insert("jt".to_owned(), vec![0xAA; 6]);
+        let (data, info) = fw.with_jump_table();
+        assert_eq!(data.len(), 0x28);
+        assert_eq!(&data[0x1E..0x20], [0, 0]);
+        assert_eq!(&data[0x20..], [0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0, 0]);
+        assert_eq!((info.jt_offset, info.jt_size), (8, 2));
+    }
+
+    #[test]
+    fn writes_jump_table() {
+        let mut fw = fixtures::firmware("Hawaii_mec", BASE, (0..0x20).collect());
+        fw.parts.insert("jt".to_owned(), vec![0xAA; 8]);
+        let raw = OutputFormat::Raw.encode(&fw).unwrap();
+        assert_eq!(
+            raw,
+            [
+                (String::new(), fw.data.clone()),
+                ("_jt".to_owned(), vec![0xAA; 8])
+            ]
+        );
+
+        // amdgpu output has no `_jt` file, the table follows the ucode and the header points at it.
+        let amdgpu = OutputFormat::Amdgpu.encode(&fw).unwrap();
+        assert_eq!(amdgpu.len(), 1);
+        let header = &amdgpu[0].1;
+        let u32_at =
+            |offset: usize| u32::from_le_bytes(header[offset..offset + 4].try_into().unwrap());
+        // `jt_offset` and `jt_size` follow the common header and the feature version.
+        assert_eq!((u32_at(0x24), u32_at(0x28)), (8, 2));
+        assert_eq!(&header[0x2C..], [fw.data.as_slice(), &[0xAA; 8]].concat());
+
+        // A jump table inside the ucode is written out too with raw output.
+        fw.parts.clear();
+        fw.info.jt_offset = 4;
+        fw.info.jt_size = 2;
+        let raw = OutputFormat::Raw.encode(&fw).unwrap();
+        assert_eq!(raw[1], ("_jt".to_owned(), fw.data[0x10..0x18].to_vec()));
+    }
+}
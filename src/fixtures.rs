@@ -0,0 +1,493 @@
+//! Copyright © 2024 ChefKiss Inc. Licensed under the Thou Shalt Not Profit License version 1.5.
+//! See LICENSE for details.
+
+//! Minimal driver images for the tests, built by hand as `object` can only write them with dependencies we don't have.
+
+use std::{collections::BTreeMap, path::PathBuf};
+
+use crate::{
+    firmware::{Extractor, Firmware, FirmwareType},
+    layout::Layout,
+};
+
+pub const PE_FILE_ALIGNMENT: usize = 0x200;
+pub const PE_HEADERS_SIZE: usize = 0x400;
+pub const IMAGE_DIRECTORY_ENTRY_EXPORT: usize = 0;
+pub const IMAGE_DIRECTORY_ENTRY_SECURITY: usize = 4;
+pub const IMAGE_DIRECTORY_ENTRY_BASERELOC: usize = 5;
+/// Where `pe64` puts the checksum: the PE signature at 0x80, the file header and 64 bytes into the optional header.
+pub const PE_CHECKSUM_OFFSET: usize = 0x80 + 4 + 20 + 64;
+/// Where `pe64` puts the data directories, 112 bytes into the optional header.
+pub const PE_DATA_DIRECTORIES: usize = 0x80 + 4 + 20 + 112;
+/// Where `pe64` puts the section headers, after the 0xF0 byte optional header.
+pub const PE_SECTION_TABLE: usize = 0x80 + 4 + 20 + 0xF0;
+
+/// An empty directory for a test to write to, unique to `name` and the test process.
+pub fn temp_dir(name: &str) -> PathBuf {
+    let ret = std::env::temp_dir().join(format!("catalyst-fw-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&ret);
+    std::fs::create_dir_all(&ret).unwrap();
+    ret
+}
+
+/// The built-in layout `name`.
+pub fn layout(name: &str) -> Layout {
+    Layout::find(&Layout::builtin(), name).unwrap().clone()
+}
+
+/// An extractor for the built-in layout `name`.
+pub fn extractor(name: &str) -> Extractor {
+    Extractor::new(layout(name))
+}
+
+/// Section contents with a descriptor at 0x10, its size field at `size_at` holding 0x40, and a 0x40 byte blob at 0x100
+/// for its pointer to be relocated to.
+pub fn descriptor_data(size_at: usize) -> Vec<u8> {
+    let mut ret = vec![0; 0x200];
+    ret[0x10 + size_at..0x14 + size_at].copy_from_slice(&0x40u32.to_le_bytes());
+    for (i, v) in ret[0x100..0x140].iter_mut().enumerate() {
+        *v = i as u8;
+    }
+    ret
+}
+
+/// A GC blob as extracted through the descriptor at `address`, pointing just past it, classified by `name`.
+pub fn firmware(name: &str, address: u64, data: Vec<u8>) -> Firmware {
+    let mut ret = Firmware {
+        name: name.to_owned(),
+        layout: "GC".to_owned(),
+        address,
+        ty: FirmwareType::Gc,
+        fw_off: address + 0x100,
+        file_offset: None,
+        data,
+        info: Default::default(),
+        parts: BTreeMap::new(),
+        values: BTreeMap::new(),
+        class: None,
+    };
+    ret.classify(None);
+    ret
+}
+
+fn put(data: &mut [u8], offset: usize, bytes: &[u8]) {
+    data[offset..offset + bytes.len()].copy_from_slice(bytes);
+}
+
+/// RVA of the `index`th section of `pe64`.
+pub const fn pe_rva(index: usize) -> u32 {
+    0x1000 * (index as u32 + 1)
+}
+
+/// A PE32+ image with `sections` mapped one page apart from RVA 0x1000, `.text` as code, and the data directories in
+/// `directories` as (index, RVA, size). The checksum is set, so patching has to recompute it.
+pub fn pe64(
+    image_base: u64,
+    sections: &[(&str, Vec<u8>)],
+    directories: &[(usize, u32, u32)],
+) -> Vec<u8> {
+    let mut ret = vec![0; PE_HEADERS_SIZE];
+    put(&mut ret, 0, b"MZ");
+    put(&mut ret, 0x3C, &0x80u32.to_le_bytes());
+    put(&mut ret, 0x80, b"PE\0\0");
+    let file_header = 0x84;
+    put(&mut ret, file_header, &0x8664u16.to_le_bytes());
+    put(
+        &mut ret,
+        file_header + 2,
+        &(sections.len() as u16).to_le_bytes(),
+    );
+    put(&mut ret, file_header + 16, &0xF0u16.to_le_bytes());
+    put(&mut ret, file_header + 18, &0x22u16.to_le_bytes());
+    let optional = file_header + 20;
+    put(&mut ret, optional, &0x20Bu16.to_le_bytes());
+    put(&mut ret, optional + 24, &image_base.to_le_bytes());
+    put(&mut ret, optional + 32, &0x1000u32.to_le_bytes());
+    put(
+        &mut ret,
+        optional + 36,
+        &(PE_FILE_ALIGNMENT as u32).to_le_bytes(),
+    );
+    put(
+        &mut ret,
+        optional + 56,
+        &pe_rva(sections.len()).to_le_bytes(),
+    );
+    put(
+        &mut ret,
+        optional + 60,
+        &(PE_HEADERS_SIZE as u32).to_le_bytes(),
+    );
+    put(&mut ret, optional + 64, &1u32.to_le_bytes());
+    // Native subsystem, as drivers are.
+    put(&mut ret, optional + 68, &1u16.to_le_bytes());
+    put(&mut ret, optional + 108, &16u32.to_le_bytes());
+    for &(index, rva, size) in directories {
+        put(&mut ret, optional + 112 + 8 * index, &rva.to_le_bytes());
+        put(&mut ret, optional + 116 + 8 * index, &size.to_le_bytes());
+    }
+
+    let table = optional + 0xF0;
+    for (i, (name, data)) in sections.iter().enumerate() {
+        let raw = ret.len();
+        let raw_size = data.len().next_multiple_of(PE_FILE_ALIGNMENT);
+        ret.extend_from_slice(data);
+        ret.resize(raw + raw_size, 0);
+
+        let header = table + 40 * i;
+        put(&mut ret, header, name.as_bytes());
+        for (j, v) in [data.len(), pe_rva(i) as usize, raw_size, raw]
+            .into_iter()
+            .enumerate()
+        {
+            put(&mut ret, header + 8 + 4 * j, &(v as u32).to_le_bytes());
+        }
+        let characteristics: u32 = if *name == ".text" {
+            // IMAGE_SCN_CNT_CODE | IMAGE_SCN_MEM_EXECUTE | IMAGE_SCN_MEM_READ
+            0x6000_0020
+        } else {
+            // IMAGE_SCN_CNT_INITIALIZED_DATA | IMAGE_SCN_MEM_READ | IMAGE_SCN_MEM_WRITE
+            0xC000_0040
+        };
+        put(&mut ret, header + 36, &characteristics.to_le_bytes());
+    }
+    ret
+}
+
+/// A base relocation block for the page at `page`, with an `IMAGE_REL_BASED_DIR64` entry per offset, padded to a
+/// dword with an `IMAGE_REL_BASED_ABSOLUTE` one.
+pub fn pe_relocations(page: u32, offsets: &[u16]) -> Vec<u8> {
+    let entries = offsets.len().next_multiple_of(2);
+    let mut ret = Vec::new();
+    ret.extend_from_slice(&page.to_le_bytes());
+    ret.extend_from_slice(&(8 + 2 * entries as u32).to_le_bytes());
+    for &offset in offsets {
+        ret.extend_from_slice(&((10 << 12) | offset).to_le_bytes());
+    }
+    ret.resize(8 + 2 * entries, 0);
+    ret
+}
+
+/// Appends `value` as a native word of a 32 or 64-bit image.
+fn word(data: &mut Vec<u8>, value: u64, is_64: bool) {
+    if is_64 {
+        data.extend_from_slice(&value.to_le_bytes());
+    } else {
+        data.extend_from_slice(&(value as u32).to_le_bytes());
+    }
+}
+
+/// An ELF section as (name, type, flags, contents, link, info, align, entsize).
+type ElfSection = (u32, u32, u64, Vec<u8>, u32, u32, u64, u64);
+
+/// A relocatable x86-64 or i386 object with `data` in `.data` after a `.text` section, an absolute pointer relocation
+/// against `.data` per (offset, addend), and a global symbol in `.data` per (name, offset).
+pub fn elf(
+    is_64: bool,
+    mut data: Vec<u8>,
+    relocations: &[(u64, i64)],
+    symbols: &[(&str, u64)],
+) -> Vec<u8> {
+    const SHT_PROGBITS: u32 = 1;
+    const SHT_SYMTAB: u32 = 2;
+    const SHT_STRTAB: u32 = 3;
+    const SHT_RELA: u32 = 4;
+    const SHT_REL: u32 = 9;
+    const SHF_WRITE: u64 = 1;
+    const SHF_ALLOC: u64 = 2;
+    const SHF_EXECINSTR: u64 = 4;
+
+    let mut strtab = vec![0];
+    let mut symtab = Vec::new();
+    let mut symbol = |name: u32, info: u8, shndx: u16, value: u64| {
+        symtab.extend_from_slice(&name.to_le_bytes());
+        if is_64 {
+            symtab.extend_from_slice(&[info, 0]);
+            symtab.extend_from_slice(&shndx.to_le_bytes());
+            symtab.extend_from_slice(&value.to_le_bytes());
+            symtab.extend_from_slice(&0u64.to_le_bytes());
+        } else {
+            symtab.extend_from_slice(&(value as u32).to_le_bytes());
+            symtab.extend_from_slice(&0u32.to_le_bytes());
+            symtab.extend_from_slice(&[info, 0]);
+            symtab.extend_from_slice(&shndx.to_le_bytes());
+        }
+    };
+    symbol(0, 0, 0, 0);
+    // STB_LOCAL | STT_SECTION for `.data`, which the relocations are against.
+    symbol(0, 3, 2, 0);
+    for &(name, offset) in symbols {
+        let name_offset = strtab.len() as u32;
+        strtab.extend_from_slice(name.as_bytes());
+        strtab.push(0);
+        // STB_GLOBAL | STT_OBJECT
+        symbol(name_offset, 0x11, 2, offset);
+    }
+
+    let mut rel = Vec::new();
+    for &(offset, addend) in relocations {
+        word(&mut rel, offset, is_64);
+        if is_64 {
+            // R_X86_64_64 against symbol 1
+            rel.extend_from_slice(&((1u64 << 32) | 1).to_le_bytes());
+            rel.extend_from_slice(&addend.to_le_bytes());
+        } else {
+            // R_386_32 against symbol 1, with the addend in place
+            rel.extend_from_slice(&((1u32 << 8) | 1).to_le_bytes());
+            let slot = offset as usize;
+            data[slot..slot + 4].copy_from_slice(&(addend as u32).to_le_bytes());
+        }
+    }
+
+    let shstrtab = b"\0.text\0.data\0.rela.data\0.rel.data\0.symtab\0.strtab\0.shstrtab\0".to_vec();
+    let (rel_name, rel_type, rel_size) = if is_64 {
+        (13, SHT_RELA, 24)
+    } else {
+        (24, SHT_REL, 8)
+    };
+    let (ehsize, shentsize, symsize) = if is_64 { (64, 64, 24) } else { (52, 40, 16) };
+    let sections: [ElfSection; 6] = [
+        (
+            1,
+            SHT_PROGBITS,
+            SHF_ALLOC | SHF_EXECINSTR,
+            vec![0xC3; 0x10],
+            0,
+            0,
+            16,
+            0,
+        ),
+        (7, SHT_PROGBITS, SHF_ALLOC | SHF_WRITE, data, 0, 0, 8, 0),
+        (rel_name, rel_type, 0, rel, 4, 2, 8, rel_size),
+        (34, SHT_SYMTAB, 0, symtab, 5, 2, 8, symsize),
+        (42, SHT_STRTAB, 0, strtab, 0, 0, 1, 0),
+        (50, SHT_STRTAB, 0, shstrtab, 0, 0, 1, 0),
+    ];
+
+    let mut ret = vec![0; ehsize];
+    let mut offsets = Vec::new();
+    for section in &sections {
+        ret.resize(ret.len().next_multiple_of(8), 0);
+        offsets.push(ret.len() as u64);
+        ret.extend_from_slice(&section.3);
+    }
+    ret.resize(ret.len().next_multiple_of(8), 0);
+    let shoff = ret.len() as u64;
+    ret.resize(ret.len() + shentsize, 0);
+    for ((name, ty, flags, contents, link, info, align, entsize), offset) in
+        sections.iter().zip(offsets)
+    {
+        ret.extend_from_slice(&name.to_le_bytes());
+        ret.extend_from_slice(&ty.to_le_bytes());
+        word(&mut ret, *flags, is_64);
+        word(&mut ret, 0, is_64);
+        word(&mut ret, offset, is_64);
+        word(&mut ret, contents.len() as u64, is_64);
+        ret.extend_from_slice(&link.to_le_bytes());
+        ret.extend_from_slice(&info.to_le_bytes());
+        word(&mut ret, *align, is_64);
+        word(&mut ret, *entsize, is_64);
+    }
+
+    let mut header = vec![0x7F, b'E', b'L', b'F', if is_64 { 2 } else { 1 }, 1, 1];
+    header.resize(16, 0);
+    // ET_REL
+    header.extend_from_slice(&1u16.to_le_bytes());
+    header.extend_from_slice(&(if is_64 { 62u16 } else { 3 }).to_le_bytes());
+    header.extend_from_slice(&1u32.to_le_bytes());
+    word(&mut header, 0, is_64);
+    word(&mut header, 0, is_64);
+    word(&mut header, shoff, is_64);
+    header.extend_from_slice(&0u32.to_le_bytes());
+    for v in [ehsize, 0, 0, shentsize, sections.len() + 1, sections.len()] {
+        header.extend_from_slice(&(v as u16).to_le_bytes());
+    }
+    ret[..ehsize].copy_from_slice(&header);
+    ret
+}
+
+/// Where `macho` links `__TEXT`, with `__DATA` a page above it.
+pub const MACHO_BASE: u64 = 0x10000;
+pub const MACHO_DATA: u64 = MACHO_BASE + 0x1000;
+
+/// A x86-64 or i386 kext with `data` in `__DATA,__data`, a chained pointer per (offset, target) in it that is rebased
+/// to `target` or, for `None`, binds to another kext, and a global symbol in `__data` per (name, offset), prefixed
+/// with `_`. Without pointers, there is no `LC_DYLD_CHAINED_FIXUPS`.
+pub fn macho(
+    is_64: bool,
+    mut data: Vec<u8>,
+    pointers: &[(u32, Option<u64>)],
+    symbols: &[(&str, u64)],
+) -> Vec<u8> {
+    const LC_SEGMENT: u32 = 0x1;
+    const LC_SYMTAB: u32 = 0x2;
+    const LC_SEGMENT_64: u32 = 0x19;
+    const LC_DYLD_CHAINED_FIXUPS: u32 = 0x8000_0034;
+    const DYLD_CHAINED_PTR_32: u16 = 3;
+    const DYLD_CHAINED_PTR_64_OFFSET: u16 = 6;
+    const PAGE: usize = 0x1000;
+
+    // The chains, each pointer storing the distance to the next in 4 byte strides.
+    for (i, &(offset, target)) in pointers.iter().enumerate() {
+        let next = pointers
+            .get(i + 1)
+            .map_or(0, |v| u64::from(v.0 - offset) / 4);
+        let slot = offset as usize;
+        if is_64 {
+            let raw = match target {
+                Some(v) => v - MACHO_BASE,
+                None => 1 << 63,
+            } | (next << 51);
+            data[slot..slot + 8].copy_from_slice(&raw.to_le_bytes());
+        } else {
+            let raw = match target {
+                Some(v) => v as u32,
+                None => 1 << 31,
+            } | ((next as u32) << 26);
+            data[slot..slot + 4].copy_from_slice(&raw.to_le_bytes());
+        }
+    }
+    let data_size = data.len().next_multiple_of(PAGE);
+    data.resize(data_size, 0);
+
+    let mut fixups = Vec::new();
+    if let Some(&(first, _)) = pointers.first() {
+        // dyld_chained_fixups_header, then dyld_chained_starts_in_image for `__TEXT` and `__DATA`.
+        for v in [0u32, 32, 0, 0, 0, 1, 0, 0, 2, 0, 12] {
+            fixups.extend_from_slice(&v.to_le_bytes());
+        }
+        // dyld_chained_starts_in_segment of `__DATA`, with a single page.
+        fixups.extend_from_slice(&24u32.to_le_bytes());
+        fixups.extend_from_slice(&(PAGE as u16).to_le_bytes());
+        let format = if is_64 {
+            DYLD_CHAINED_PTR_64_OFFSET
+        } else {
+            DYLD_CHAINED_PTR_32
+        };
+        fixups.extend_from_slice(&format.to_le_bytes());
+        fixups.extend_from_slice(&(MACHO_DATA - MACHO_BASE).to_le_bytes());
+        fixups.extend_from_slice(&0x10_0000u32.to_le_bytes());
+        fixups.extend_from_slice(&1u16.to_le_bytes());
+        fixups.extend_from_slice(&(first as u16).to_le_bytes());
+    }
+
+    let mut strtab = vec![b' ', 0];
+    let mut symtab = Vec::new();
+    for &(name, offset) in symbols {
+        symtab.extend_from_slice(&(strtab.len() as u32).to_le_bytes());
+        strtab.push(b'_');
+        strtab.extend_from_slice(name.as_bytes());
+        strtab.push(0);
+        // N_SECT | N_EXT, in section 2, `__data`.
+        symtab.extend_from_slice(&[0xF, 2, 0, 0]);
+        word(&mut symtab, MACHO_DATA + offset, is_64);
+    }
+
+    let fixups_off = PAGE + data_size;
+    let symoff = fixups_off + fixups.len().next_multiple_of(8);
+    let stroff = symoff + symtab.len();
+
+    let mut commands = Vec::new();
+    let mut ncmds = 0;
+    let text = vec![0xC3; 0x10];
+    let text_off = 0x800;
+    for (segname, sectname, vmaddr, fileoff, filesize, contents_off, contents_size) in [
+        (
+            "__TEXT",
+            "__text",
+            MACHO_BASE,
+            0,
+            PAGE,
+            text_off,
+            text.len(),
+        ),
+        (
+            "__DATA", "__data", MACHO_DATA, PAGE, data_size, PAGE, data_size,
+        ),
+    ] {
+        let (cmd, cmdsize) = if is_64 {
+            (LC_SEGMENT_64, 72 + 80)
+        } else {
+            (LC_SEGMENT, 56 + 68)
+        };
+        commands.extend_from_slice(&cmd.to_le_bytes());
+        commands.extend_from_slice(&(cmdsize as u32).to_le_bytes());
+        let mut name = [0; 16];
+        name[..segname.len()].copy_from_slice(segname.as_bytes());
+        commands.extend_from_slice(&name);
+        for v in [vmaddr, filesize as u64, fileoff as u64, filesize as u64] {
+            word(&mut commands, v, is_64);
+        }
+        // maxprot, initprot, nsects, flags
+        for v in [7u32, 7, 1, 0] {
+            commands.extend_from_slice(&v.to_le_bytes());
+        }
+        let mut name = [0; 16];
+        name[..sectname.len()].copy_from_slice(sectname.as_bytes());
+        commands.extend_from_slice(&name);
+        let mut name = [0; 16];
+        name[..segname.len()].copy_from_slice(segname.as_bytes());
+        commands.extend_from_slice(&name);
+        word(
+            &mut commands,
+            vmaddr + (contents_off - fileoff) as u64,
+            is_64,
+        );
+        word(&mut commands, contents_size as u64, is_64);
+        // offset, align, reloff, nreloc, flags, reserved1-2(-3)
+        let flags = if sectname == "__text" { 0x8000_0400 } else { 0 };
+        for v in [contents_off as u32, 4, 0, 0, flags, 0, 0] {
+            commands.extend_from_slice(&v.to_le_bytes());
+        }
+        if is_64 {
+            commands.extend_from_slice(&0u32.to_le_bytes());
+        }
+        ncmds += 1;
+    }
+    for v in [
+        LC_SYMTAB,
+        24,
+        symoff as u32,
+        symbols.len() as u32,
+        stroff as u32,
+        strtab.len() as u32,
+    ] {
+        commands.extend_from_slice(&v.to_le_bytes());
+    }
+    ncmds += 1;
+    if !fixups.is_empty() {
+        for v in [
+            LC_DYLD_CHAINED_FIXUPS,
+            16,
+            fixups_off as u32,
+            fixups.len() as u32,
+        ] {
+            commands.extend_from_slice(&v.to_le_bytes());
+        }
+        ncmds += 1;
+    }
+
+    let mut ret = Vec::new();
+    let (magic, cputype): (u32, u32) = if is_64 {
+        (0xFEED_FACF, 0x0100_0007)
+    } else {
+        (0xFEED_FACE, 7)
+    };
+    // MH_KEXT_BUNDLE
+    for v in [magic, cputype, 3, 0xB, ncmds, commands.len() as u32, 0] {
+        ret.extend_from_slice(&v.to_le_bytes());
+    }
+    if is_64 {
+        ret.extend_from_slice(&0u32.to_le_bytes());
+    }
+    ret.extend_from_slice(&commands);
+    ret.resize(text_off, 0);
+    ret.extend_from_slice(&text);
+    ret.resize(PAGE, 0);
+    ret.extend_from_slice(&data);
+    ret.extend_from_slice(&fixups);
+    ret.resize(symoff, 0);
+    ret.extend_from_slice(&symtab);
+    ret.extend_from_slice(&strtab);
+    ret
+}
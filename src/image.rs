@@ -0,0 +1,155 @@
+//! Copyright © 2024 ChefKiss Inc. Licensed under the Thou Shalt Not Profit License version 1.5.
+//! See LICENSE for details.
+
+use std::{collections::BTreeMap, ops::Range};
+
+use crate::source::{ByteSource, Endianness, SymbolInfo, SymbolSource};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SegmentKind {
+    Code,
+    Data,
+}
+
+#[derive(Debug, Clone)]
+pub struct Segment {
+    pub address: u64,
+    pub data: Vec<u8>,
+    pub kind: SegmentKind,
+    /// Where the segment starts in the file it was loaded from, if it is backed by one.
+    pub file_offset: Option<u64>,
+}
+
+impl Segment {
+    fn end(&self) -> u64 {
+        self.address + self.data.len() as u64
+    }
+}
+
+/// An in-memory address space, for driving the extractor without Binary Ninja.
+#[derive(Debug, Clone)]
+pub struct Image {
+    /// Address the image is loaded at, e.g. the PE image base.
+    base: u64,
+    segments: Vec<Segment>,
+    symbols: BTreeMap<u64, String>,
+    pointer_width: usize,
+    endianness: Endianness,
+}
+
+impl Image {
+    pub fn new(base: u64, pointer_width: usize, endianness: Endianness) -> Self {
+        Self {
+            base,
+            segments: Vec::new(),
+            symbols: BTreeMap::new(),
+            pointer_width,
+            endianness,
+        }
+    }
+
+    pub fn flat(base: u64, data: Vec<u8>, pointer_width: usize, endianness: Endianness) -> Self {
+        let mut ret = Self::new(base, pointer_width, endianness);
+        ret.add_segment(base, data, SegmentKind::Data, Some(0));
+        ret
+    }
+
+    pub fn add_segment(
+        &mut self,
+        address: u64,
+        data: Vec<u8>,
+        kind: SegmentKind,
+        file_offset: Option<u64>,
+    ) {
+        self.segments.push(Segment {
+            address,
+            data,
+            kind,
+            file_offset,
+        });
+    }
+
+    pub fn add_symbol(&mut self, address: u64, name: impl Into<String>) {
+        self.symbols.insert(address, name.into());
+    }
+
+    /// Moves every segment's file offset by `offset`, for images loaded from a slice of a larger file such as a fat
+    /// binary.
+    pub fn shift_file_offsets(&mut self, offset: u64) {
+        for segment in &mut self.segments {
+            if let Some(v) = &mut segment.file_offset {
+                *v += offset;
+            }
+        }
+    }
+
+    pub fn base(&self) -> u64 {
+        self.base
+    }
+
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+
+    pub fn find_symbol(&self, name: &str) -> Option<u64> {
+        self.symbols
+            .iter()
+            .find(|(_, v)| {
+                v.as_str() == name
+                    || v.strip_prefix('_') == Some(name)
+                    || name.strip_prefix('_') == Some(v.as_str())
+            })
+            .map(|(&addr, _)| addr)
+    }
+
+    fn segment_of(&self, offset: u64) -> Option<&Segment> {
+        self.segments
+            .iter()
+            .find(|v| v.address <= offset && offset < v.end())
+    }
+}
+
+impl ByteSource for Image {
+    fn read_bytes(&self, offset: u64, len: usize) -> Vec<u8> {
+        let Some(segment) = self.segment_of(offset) else {
+            return Vec::new();
+        };
+        let start = (offset - segment.address) as usize;
+        let end = start.saturating_add(len).min(segment.data.len());
+        segment.data[start..end].to_vec()
+    }
+
+    fn contains(&self, offset: u64) -> bool {
+        self.segment_of(offset).is_some()
+    }
+
+    fn pointer_width(&self) -> usize {
+        self.pointer_width
+    }
+
+    fn endianness(&self) -> Endianness {
+        self.endianness
+    }
+
+    fn file_offset(&self, offset: u64) -> Option<u64> {
+        let segment = self.segment_of(offset)?;
+        Some(segment.file_offset? + (offset - segment.address))
+    }
+
+    fn data_ranges(&self) -> Vec<Range<u64>> {
+        self.segments
+            .iter()
+            .filter(|v| v.kind == SegmentKind::Data)
+            .map(|v| v.address..v.end())
+            .collect()
+    }
+}
+
+impl SymbolSource for Image {
+    fn symbol_at(&self, offset: u64) -> Option<SymbolInfo> {
+        self.symbols.get(&offset).map(|name| SymbolInfo {
+            name: name.clone(),
+            address: offset,
+        })
+    }
+}
@@ -0,0 +1,172 @@
+//! Copyright © 2024 ChefKiss Inc. Licensed under the Thou Shalt Not Profit License version 1.5.
+//! See LICENSE for details.
+
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::{
+    error::{Error, Result},
+    firmware::Extractor,
+    layout::Layout,
+    scan,
+    source::{ByteSource, SymbolSource},
+};
+
+/// Maps descriptor symbol names to a layout.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Pattern {
+    /// Case-insensitive substring of the symbol name. The name is wrapped in `_`, so `_me_` matches a whole word.
+    #[serde(rename = "match")]
+    pub pattern: String,
+    pub layout: String,
+}
+
+impl Pattern {
+    fn new(pattern: &str, layout: &str) -> Self {
+        Self {
+            pattern: pattern.to_owned(),
+            layout: layout.to_owned(),
+        }
+    }
+
+    pub fn builtin() -> Vec<Self> {
+        vec![
+            Self::new("rlc", "RLC"),
+            Self::new("sdma", "SDMA"),
+            Self::new("mec", "MEC"),
+            Self::new("pfp", "GC"),
+            Self::new("_me_", "GC"),
+            Self::new("_ce_", "GC"),
+            Self::new("gfx", "GC"),
+        ]
+    }
+
+    pub fn parse(s: &str) -> std::result::Result<Vec<Self>, toml::de::Error> {
+        #[derive(Deserialize)]
+        struct PatternFile {
+            #[serde(default)]
+            pattern: Vec<Pattern>,
+        }
+
+        toml::from_str::<PatternFile>(s).map(|v| v.pattern)
+    }
+
+    /// The patterns in `path`, which take precedence over the built-in ones.
+    pub fn load(path: &Path) -> std::result::Result<Vec<Self>, String> {
+        let s = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {e}", path.display()))?;
+        let mut ret =
+            Self::parse(&s).map_err(|e| format!("Failed to parse {}: {e}", path.display()))?;
+        ret.extend(Self::builtin());
+        Ok(ret)
+    }
+
+    pub fn matches(&self, name: &str) -> bool {
+        format!("_{}_", Extractor::sym_to_fw_name(name).to_ascii_lowercase())
+            .contains(&self.pattern.to_ascii_lowercase())
+    }
+}
+
+/// Picks the layout of the descriptor at `offset`: the one named by the first pattern matching its symbol if it
+/// validates, otherwise the best scoring of those that do. Unlike when scanning, blobs too small or out of place to be
+/// found that way still count, as the caller says there is a descriptor here.
+pub fn infer<'a, S: ByteSource + SymbolSource + ?Sized>(
+    src: &S,
+    layouts: &'a [Layout],
+    patterns: &[Pattern],
+    offset: u64,
+) -> Result<&'a Layout> {
+    let address = Extractor::fw_info_addr(src, offset);
+    if let Some(sym) = src.symbol_at(address) {
+        let named = patterns
+            .iter()
+            .filter(|v| v.matches(&sym.name))
+            .find_map(|v| Layout::find(layouts, &v.layout));
+        if let Some(layout) = named {
+            if Extractor::new(layout.clone()).fw_valid(src, address) {
+                return Ok(layout);
+            }
+        }
+    }
+    layouts
+        .iter()
+        .filter(|v| Extractor::new((*v).clone()).fw_valid(src, address))
+        .filter_map(|v| scan::rate(src, v, address))
+        // `max_by_key` keeps the last of equal scores, so ties go to the layout listed first.
+        .rev()
+        .max_by_key(|v| v.score)
+        .map(|v| v.layout)
+        .ok_or(Error::NoMatchingLayout { address })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        image::{Image, SegmentKind},
+        source::Endianness,
+    };
+
+    const DATA: u64 = 0x10000;
+    const DESCRIPTOR: u64 = DATA + 0x40;
+    const BLOB: u64 = DATA + 0x400;
+
+    /// Data with a GC descriptor at `DESCRIPTOR` for `size` bytes at `BLOB`, and an SDMA one right after it.
+    fn image(size: u32, symbol: &str) -> Image {
+        let mut data = vec![0; 0x1000];
+        let at = (DESCRIPTOR - DATA) as usize;
+        data[at + 0xC..at + 0x10].copy_from_slice(&size.to_le_bytes());
+        data[at + 0x20..at + 0x28].copy_from_slice(&BLOB.to_le_bytes());
+        let at = at + 0x40;
+        data[at + 0x8..at + 0xC].copy_from_slice(&size.to_le_bytes());
+        data[at + 0x10..at + 0x18].copy_from_slice(&BLOB.to_le_bytes());
+        let mut ret = Image::new(0, 8, Endianness::Little);
+        ret.add_segment(DATA, data, SegmentKind::Data, None);
+        ret.add_symbol(DESCRIPTOR, symbol);
+        ret
+    }
+
+    #[test]
+    fn pattern_names_layout() {
+        let image = image(0x400, "_Hawaii_mec");
+        let layouts = Layout::builtin();
+        let layout = infer(&image, &layouts, &Pattern::builtin(), DESCRIPTOR).unwrap();
+        assert_eq!(layout.name, "MEC");
+        assert!(Pattern::new("_me_", "GC").matches("_Hawaii_me"));
+        assert!(!Pattern::new("_me_", "GC").matches("_Hawaii_mec"));
+    }
+
+    #[test]
+    fn falls_back_to_scores() {
+        let layouts = Layout::builtin();
+        // The SDMA pattern matches, but its layout doesn't validate, so the scores decide.
+        let image = image(0x400, "_Hawaii_sdma");
+        let layout = infer(&image, &layouts, &Pattern::builtin(), DESCRIPTOR).unwrap();
+        assert_eq!(layout.name, "GC");
+        let layout = infer(&image, &layouts, &Pattern::builtin(), DESCRIPTOR + 0x40).unwrap();
+        assert_eq!(layout.name, "SDMA");
+    }
+
+    #[test]
+    fn small_blob() {
+        // Too small for a scan to consider, but the descriptor is still valid.
+        let image = image(0x10, "_unknown");
+        let layouts = Layout::builtin();
+        let layout = infer(&image, &layouts, &[], DESCRIPTOR).unwrap();
+        assert_eq!(layout.name, "GC");
+    }
+
+    #[test]
+    fn no_matching_layout() {
+        let image = image(0x2000, "_Hawaii_pfp");
+        let layouts = Layout::builtin();
+        assert!(matches!(
+            infer(&image, &layouts, &Pattern::builtin(), DESCRIPTOR),
+            Err(Error::NoMatchingLayout {
+                address: DESCRIPTOR
+            })
+        ));
+    }
+}
@@ -0,0 +1,334 @@
+//! Copyright © 2024 ChefKiss Inc. Licensed under the Thou Shalt Not Profit License version 1.5.
+//! See LICENSE for details.
+
+use std::{collections::BTreeMap, path::Path};
+
+use serde::Deserialize;
+
+use crate::{firmware::FirmwareType, infer::Pattern, source::ByteSource};
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Field {
+    pub offset: u64,
+    /// Offset in 32-bit drivers, where struct packing differs.
+    #[serde(default)]
+    pub offset_32: Option<u64>,
+    /// Width in bytes. Defaults to 4, or the native pointer width for pointers.
+    #[serde(default)]
+    pub width: Option<usize>,
+}
+
+impl Field {
+    pub const fn new(offset: u64) -> Self {
+        Self {
+            offset,
+            offset_32: None,
+            width: None,
+        }
+    }
+
+    pub fn offset(&self, pointer_width: usize) -> u64 {
+        match (pointer_width, self.offset_32) {
+            (4, Some(offset)) => offset,
+            _ => self.offset,
+        }
+    }
+
+    pub fn width(&self, default: usize) -> usize {
+        self.width.unwrap_or(default)
+    }
+
+    /// Reads the field of the descriptor at `base`, zero-extended.
+    pub fn read<S: ByteSource + ?Sized>(
+        &self,
+        src: &S,
+        base: u64,
+        default_width: usize,
+    ) -> Option<u64> {
+        let data = src.read_bytes(
+            base + self.offset(src.pointer_width()),
+            self.width(default_width),
+        );
+        src.endianness().read_uint(&data)
+    }
+}
+
+/// A secondary blob referenced by the descriptor, e.g. an RLC save/restore list.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Part {
+    pub size: Field,
+    pub pointer: Field,
+}
+
+/// A descriptor field, resolved for a given pointer width.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Member {
+    pub name: String,
+    pub offset: u64,
+    pub width: usize,
+    pub pointer: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Layout {
+    pub name: String,
+    /// Which IP block the blob belongs to, selects the amdgpu header and name hints.
+    #[serde(rename = "type")]
+    pub ty: FirmwareType,
+    pub size: Field,
+    pub pointer: Field,
+    #[serde(default)]
+    pub version: Option<Field>,
+    #[serde(default)]
+    pub feature_version: Option<Field>,
+    /// Jump table offset in dwords from the start of the ucode.
+    #[serde(default)]
+    pub jt_offset: Option<Field>,
+    /// Jump table size in dwords.
+    #[serde(default)]
+    pub jt_size: Option<Field>,
+    /// Version word inside the ucode itself, offset from its start, for descriptors without a version field.
+    #[serde(default)]
+    pub embedded_version: Option<Field>,
+    #[serde(default)]
+    pub embedded_feature_version: Option<Field>,
+    #[serde(default)]
+    pub parts: BTreeMap<String, Part>,
+    /// Extra scalar fields, e.g. the RLC header offsets.
+    #[serde(default)]
+    pub values: BTreeMap<String, Field>,
+}
+
+impl Layout {
+    fn new(name: &str, ty: FirmwareType, size: Field, pointer: Field) -> Self {
+        Self {
+            name: name.to_owned(),
+            ty,
+            size,
+            pointer,
+            version: None,
+            feature_version: None,
+            jt_offset: None,
+            jt_size: None,
+            embedded_version: None,
+            embedded_feature_version: None,
+            parts: BTreeMap::new(),
+            values: BTreeMap::new(),
+        }
+    }
+
+    /// End of the pointer field, used to reject blobs pointing into their own descriptor.
+    pub fn descriptor_size(&self, pointer_width: usize) -> u64 {
+        self.pointer.offset(pointer_width) + self.pointer.width(pointer_width) as u64
+    }
+
+    /// Every known field of the descriptor, sorted by offset.
+    pub fn members(&self, pointer_width: usize) -> Vec<Member> {
+        let member = |name: String, field: &Field, pointer: bool| Member {
+            name,
+            offset: field.offset(pointer_width),
+            width: field.width(if pointer { pointer_width } else { 4 }),
+            pointer,
+        };
+        let mut ret = vec![
+            member("size".to_owned(), &self.size, false),
+            member("pointer".to_owned(), &self.pointer, true),
+        ];
+        for (name, field) in [
+            ("version", &self.version),
+            ("feature_version", &self.feature_version),
+            ("jt_offset", &self.jt_offset),
+            ("jt_size", &self.jt_size),
+        ] {
+            if let Some(field) = field {
+                ret.push(member(name.to_owned(), field, false));
+            }
+        }
+        for (name, part) in &self.parts {
+            ret.push(member(format!("{name}_size"), &part.size, false));
+            ret.push(member(format!("{name}_pointer"), &part.pointer, true));
+        }
+        for (name, field) in &self.values {
+            ret.push(member(name.clone(), field, false));
+        }
+        ret.sort_by_key(|v| v.offset);
+        ret
+    }
+
+    /// The layouts known to Catalyst drivers. None of them has version fields, as where they are kept is unknown, so
+    /// versions only come from an amdgpu header left on the blob unless a layouts file adds them.
+    pub fn builtin() -> Vec<Self> {
+        vec![
+            Self::new("GC", FirmwareType::Gc, Field::new(0xC), Field::new(0x20)),
+            // The SDMA pointer directly follows the size field, so it loses its alignment padding on 32-bit builds.
+            Self::new(
+                "SDMA",
+                FirmwareType::Sdma,
+                Field::new(0x8),
+                Field {
+                    offset_32: Some(0xC),
+                    ..Field::new(0x10)
+                },
+            ),
+            // MEC and RLC descriptors start like GC ones. Where they keep the jump table, the save/restore lists and
+            // the `rlc_firmware_header_v2_x` offsets is unknown, so their `parts` and `values` have to come from a
+            // layouts file, without which amdgpu RLC output is refused.
+            Self::new("MEC", FirmwareType::Gc, Field::new(0xC), Field::new(0x20)),
+            Self::new("RLC", FirmwareType::Rlc, Field::new(0xC), Field::new(0x20)),
+        ]
+    }
+
+    pub fn parse(s: &str) -> Result<Vec<Self>, toml::de::Error> {
+        #[derive(Deserialize)]
+        #[serde(deny_unknown_fields)]
+        struct LayoutFile {
+            #[serde(default)]
+            layout: Vec<Layout>,
+            /// Read by `Pattern::parse`, only validated here.
+            #[serde(default)]
+            #[allow(dead_code)]
+            pattern: Vec<Pattern>,
+        }
+
+        toml::from_str::<LayoutFile>(s).map(|v| v.layout)
+    }
+
+    /// The built-in layouts, overridden by name and extended by the ones in `path`.
+    pub fn load(path: &Path) -> Result<Vec<Self>, String> {
+        let s = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {e}", path.display()))?;
+        let extra =
+            Self::parse(&s).map_err(|e| format!("Failed to parse {}: {e}", path.display()))?;
+        let mut ret = Self::builtin();
+        for layout in extra {
+            match ret
+                .iter_mut()
+                .find(|v| v.name.eq_ignore_ascii_case(&layout.name))
+            {
+                Some(v) => *v = layout,
+                None => ret.push(layout),
+            }
+        }
+        Ok(ret)
+    }
+
+    pub fn find<'a>(layouts: &'a [Self], name: &str) -> Option<&'a Self> {
+        layouts.iter().find(|v| v.name.eq_ignore_ascii_case(name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    const FILE: &str = r#"
+[[pattern]]
+match = "_ce_"
+layout = "GC"
+
+[[layout]]
+name = "gc"
+type = "gc"
+size = { offset = 0x10 }
+pointer = { offset = 0x28, offset_32 = 0x1C }
+version = { offset = 0x4, width = 2 }
+
+[[layout]]
+name = "PSP"
+type = "gc"
+size = { offset = 0x8 }
+pointer = { offset = 0x10 }
+
+[layout.parts.toc]
+size = { offset = 0x18 }
+pointer = { offset = 0x20 }
+
+[layout.values]
+toc_version = { offset = 0x28 }
+"#;
+
+    #[test]
+    fn parses_file() {
+        let layouts = Layout::parse(FILE).unwrap();
+        assert_eq!(layouts.len(), 2);
+        let gc = &layouts[0];
+        assert_eq!(gc.size, Field::new(0x10));
+        assert_eq!((gc.pointer.offset(8), gc.pointer.offset(4)), (0x28, 0x1C));
+        assert_eq!(
+            gc.version,
+            Some(Field {
+                width: Some(2),
+                ..Field::new(0x4)
+            })
+        );
+        let psp = &layouts[1];
+        assert_eq!(psp.ty, FirmwareType::Gc);
+        assert_eq!(psp.parts["toc"].pointer, Field::new(0x20));
+        assert_eq!(psp.values["toc_version"], Field::new(0x28));
+
+        for invalid in [
+            "[[layout]]\nname = \"GC\"\ntype = \"gc\"\nsize = { offset = 0 }\npointer = { offset = 8 }\nsize_32 = 4",
+            "[[layout]]\nname = \"GC\"\ntype = \"gc\"\nsize = { offset = 0, bits = 32 }\npointer = { offset = 8 }",
+            "[[layouts]]",
+        ] {
+            assert!(Layout::parse(invalid).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn overrides_builtin() {
+        let dir = fixtures::temp_dir("layouts");
+        let path = dir.join("catalyst-fw-layouts.toml");
+        std::fs::write(&path, FILE).unwrap();
+        let layouts = Layout::load(&path).unwrap();
+        std::fs::remove_dir_all(dir).unwrap();
+
+        let builtin = Layout::builtin();
+        // `gc` replaces `GC` in place, despite the case, and `PSP` is added after the built-in layouts.
+        assert_eq!(layouts.len(), builtin.len() + 1);
+        assert_eq!(layouts[0].name, "gc");
+        assert_eq!(Layout::find(&layouts, "GC").unwrap().size, Field::new(0x10));
+        assert_eq!(layouts[1..builtin.len()], builtin[1..]);
+        assert_eq!(layouts[builtin.len()].name, "PSP");
+    }
+
+    #[test]
+    fn members() {
+        let psp = &Layout::parse(FILE).unwrap()[1];
+        let members: Vec<_> = psp
+            .members(8)
+            .into_iter()
+            .map(|v| (v.name, v.offset, v.width, v.pointer))
+            .collect();
+        assert_eq!(
+            members,
+            [
+                ("size".to_owned(), 0x8, 4, false),
+                ("pointer".to_owned(), 0x10, 8, true),
+                ("toc_size".to_owned(), 0x18, 4, false),
+                ("toc_pointer".to_owned(), 0x20, 8, true),
+                ("toc_version".to_owned(), 0x28, 4, false),
+            ]
+        );
+
+        // 32-bit drivers read `offset_32` and native pointers.
+        let gc = &Layout::parse(FILE).unwrap()[0];
+        let members: Vec<_> = gc
+            .members(4)
+            .into_iter()
+            .map(|v| (v.name, v.offset, v.width))
+            .collect();
+        assert_eq!(
+            members,
+            [
+                ("version".to_owned(), 0x4, 2),
+                ("size".to_owned(), 0x10, 4),
+                ("pointer".to_owned(), 0x1C, 4),
+            ]
+        );
+    }
+}
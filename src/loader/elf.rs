@@ -0,0 +1,198 @@
+//! Copyright © 2024 ChefKiss Inc. Licensed under the Thou Shalt Not Profit License version 1.5.
+//! See LICENSE for details.
+
+use std::collections::HashMap;
+
+use object::{
+    elf::{R_386_32, R_X86_64_32, R_X86_64_32S, R_X86_64_64, SHF_ALLOC},
+    Architecture, Object, ObjectSection, ObjectSymbol, RelocationFlags, RelocationTarget,
+    SectionFlags, SectionIndex, SymbolKind,
+};
+
+use super::{endianness, is_mapped, pointer_width, segment_kind};
+use crate::image::Image;
+
+/// Where sections of relocatable objects are laid out by default, so that nothing ends up at a null address.
+const DEFAULT_BASE: u64 = 0x10000;
+
+struct Section {
+    index: SectionIndex,
+    address: u64,
+    data: Vec<u8>,
+}
+
+fn is_alloc(section: &object::Section) -> bool {
+    matches!(section.flags(), SectionFlags::Elf { sh_flags } if sh_flags & u64::from(SHF_ALLOC) != 0)
+}
+
+/// Width of the absolute relocations found in descriptor tables, in bytes.
+fn absolute_width(arch: Architecture, flags: RelocationFlags) -> Option<usize> {
+    let RelocationFlags::Elf { r_type } = flags else {
+        return None;
+    };
+    match (arch, r_type) {
+        (Architecture::X86_64, R_X86_64_64) => Some(8),
+        (Architecture::X86_64, R_X86_64_32 | R_X86_64_32S) => Some(4),
+        (Architecture::I386, R_386_32) => Some(4),
+        _ => None,
+    }
+}
+
+/// Lays out the allocated sections of a kernel module one after another from `base`, then applies the absolute
+/// relocations against them, as the module loader would.
+pub fn load(file: &object::File, base: Option<u64>) -> Image {
+    let mut next = base.unwrap_or(DEFAULT_BASE);
+    let mut addresses = HashMap::new();
+    let mut sections = Vec::new();
+    for section in file.sections() {
+        if !is_alloc(&section) {
+            continue;
+        }
+        let address = next.next_multiple_of(section.align().max(1));
+        next = address + section.size();
+        addresses.insert(section.index(), address);
+        if !is_mapped(&section) {
+            continue;
+        }
+        let Ok(data) = section.data() else {
+            continue;
+        };
+        sections.push(Section {
+            index: section.index(),
+            address,
+            data: data.to_vec(),
+        });
+    }
+
+    let symbol_address =
+        |sym: &object::Symbol| Some(addresses.get(&sym.section_index()?)? + sym.address());
+    for section in &mut sections {
+        let Ok(header) = file.section_by_index(section.index) else {
+            continue;
+        };
+        for (offset, reloc) in header.relocations() {
+            let Some(width) = absolute_width(file.architecture(), reloc.flags()) else {
+                continue;
+            };
+            let target = match reloc.target() {
+                RelocationTarget::Symbol(index) => file
+                    .symbol_by_index(index)
+                    .ok()
+                    .and_then(|v| symbol_address(&v)),
+                RelocationTarget::Section(index) => addresses.get(&index).copied(),
+                _ => None,
+            };
+            // Imports from the kernel stay unresolved.
+            let Some(target) = target else {
+                continue;
+            };
+            let Some(slot) = usize::try_from(offset)
+                .ok()
+                .and_then(|v| section.data.get_mut(v..v.checked_add(width)?))
+            else {
+                continue;
+            };
+            let addend = if reloc.has_implicit_addend() {
+                i64::from(u32::from_le_bytes(slot[..4].try_into().unwrap()))
+            } else {
+                reloc.addend()
+            };
+            let value = target.wrapping_add_signed(addend);
+            slot.copy_from_slice(&value.to_le_bytes()[..width]);
+        }
+    }
+
+    let mut ret = Image::new(
+        base.unwrap_or(DEFAULT_BASE),
+        pointer_width(file),
+        endianness(file),
+    );
+    for section in sections {
+        let Ok(header) = file.section_by_index(section.index) else {
+            continue;
+        };
+        ret.add_segment(
+            section.address,
+            section.data,
+            segment_kind(&header),
+            header.file_range().map(|(off, _)| off),
+        );
+    }
+    for sym in file.symbols() {
+        if matches!(sym.kind(), SymbolKind::Section | SymbolKind::File) {
+            continue;
+        }
+        let (Some(address), Ok(name)) = (symbol_address(&sym), sym.name()) else {
+            continue;
+        };
+        if !name.is_empty() {
+            ret.add_symbol(address, name);
+        }
+    }
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        fixtures::{self, descriptor_data, extractor},
+        image::SegmentKind,
+        loader,
+        source::ByteSource,
+    };
+
+    #[test]
+    fn lays_out_and_relocates() {
+        let object = fixtures::elf(
+            true,
+            descriptor_data(0xC),
+            &[(0x30, 0x100)],
+            &[("Hawaii_pfp", 0x10)],
+        );
+        let image = loader::load(&object, None).unwrap();
+        assert_eq!(image.pointer_width(), 8);
+        // `.text` at the default base, `.data` after it at its alignment.
+        let segments: Vec<_> = image
+            .segments()
+            .iter()
+            .map(|v| (v.address, v.kind))
+            .collect();
+        assert_eq!(
+            segments,
+            [(0x10000, SegmentKind::Code), (0x10010, SegmentKind::Data)]
+        );
+        let descriptor = image.find_symbol("Hawaii_pfp").unwrap();
+        assert_eq!(descriptor, 0x10020);
+
+        let gc = extractor("GC");
+        let fw = gc.read_fw(&image, descriptor).unwrap();
+        assert_eq!(fw.name, "Hawaii_pfp");
+        assert_eq!(fw.fw_off, 0x10110);
+        assert_eq!(fw.data, (0..0x40).collect::<Vec<u8>>());
+        assert!(fw.file_offset.is_some());
+
+        let image = loader::load(&object, Some(0x40_0000)).unwrap();
+        assert_eq!(
+            gc.read_fw_info(&image, 0x40_0020).unwrap(),
+            (0x40_0110, 0x40)
+        );
+    }
+
+    #[test]
+    fn relocates_32_bit() {
+        // The SDMA pointer directly follows the size on 32-bit builds, with the addend in place.
+        let object = fixtures::elf(
+            false,
+            descriptor_data(0x8),
+            &[(0x1C, 0x100)],
+            &[("Hawaii_sdma", 0x10)],
+        );
+        let image = loader::load(&object, None).unwrap();
+        assert_eq!(image.pointer_width(), 4);
+        let descriptor = image.find_symbol("Hawaii_sdma").unwrap();
+        let sdma = extractor("SDMA");
+        let fw = sdma.read_fw(&image, descriptor).unwrap();
+        assert_eq!(fw.fw_off, descriptor - 0x10 + 0x100);
+        assert_eq!(fw.data, (0..0x40).collect::<Vec<u8>>());
+    }
+}
@@ -0,0 +1,265 @@
+//! Copyright © 2024 ChefKiss Inc. Licensed under the Thou Shalt Not Profit License version 1.5.
+//! See LICENSE for details.
+
+use object::{
+    macho::{LinkeditDataCommand, LC_DYLD_CHAINED_FIXUPS},
+    read::macho::{FatArch, MachHeader, MachOFatFile, Segment as _},
+    Architecture, Endianness,
+};
+
+use super::load_linked;
+use crate::{
+    error::{Error, Result},
+    image::Image,
+};
+
+const DYLD_CHAINED_PTR_START_NONE: u16 = 0xFFFF;
+const DYLD_CHAINED_PTR_64: u16 = 2;
+const DYLD_CHAINED_PTR_32: u16 = 3;
+const DYLD_CHAINED_PTR_64_OFFSET: u16 = 6;
+const DYLD_CHAINED_PTR_64_KERNEL_CACHE: u16 = 8;
+const DYLD_CHAINED_PTR_X86_64_KERNEL_CACHE: u16 = 11;
+
+/// The x86-64 slice of a fat binary, or the first one if there is none, with its offset in the file.
+pub fn thin<Fat: FatArch>(data: &[u8]) -> Option<(u64, &[u8])> {
+    let arches = MachOFatFile::<Fat>::parse(data).ok()?.arches();
+    let arch = arches
+        .iter()
+        .find(|v| v.architecture() == Architecture::X86_64)
+        .or(arches.first())?;
+    Some((arch.file_range().0, arch.data(data).ok()?))
+}
+
+/// Maps a Mach-O at its linked address, with chained fixups applied and the leading `_` of symbols removed. Chained
+/// fixups that can't be followed are an error rather than leaving part of the pointers unrebased.
+pub fn load<Mach: MachHeader<Endian = Endianness>>(data: &[u8]) -> Option<Result<Image>> {
+    Mach::parse(data, 0).ok()?;
+    let mut data = data.to_vec();
+    if apply_chained_fixups::<Mach>(&mut data).is_none() {
+        return Some(Err(Error::MalformedChainedFixups));
+    }
+    let file = object::File::parse(data.as_slice()).ok()?;
+    Some(Ok(load_linked(&file, "_")))
+}
+
+struct Segment {
+    vmaddr: u64,
+    fileoff: u64,
+    filesize: u64,
+}
+
+fn read<const N: usize>(data: &[u8], offset: usize) -> Option<[u8; N]> {
+    data.get(offset..offset.checked_add(N)?)?.try_into().ok()
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    read(data, offset).map(u16::from_le_bytes)
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<usize> {
+    read(data, offset).map(|v| u32::from_le_bytes(v) as usize)
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Option<u64> {
+    read(data, offset).map(u64::from_le_bytes)
+}
+
+/// Width of the pointers of a chained fixup format, in bytes.
+fn pointer_width(format: u16) -> usize {
+    if format == DYLD_CHAINED_PTR_32 {
+        4
+    } else {
+        8
+    }
+}
+
+/// Decodes a chained pointer into its rebased value, the distance to the next one and the stride it is counted in.
+/// Binds to other kexts are left as null.
+fn decode(format: u16, raw: u64, base: u64, max_valid_pointer: u64) -> Option<(u64, u64, u64)> {
+    if format == DYLD_CHAINED_PTR_32 {
+        let next = (raw >> 26) & 0x1F;
+        let target = raw & 0x3FF_FFFF;
+        let value = if raw >> 31 != 0 {
+            0
+        } else if target > max_valid_pointer {
+            // A small integer rather than a pointer, stored biased.
+            target - (0x400_0000 + max_valid_pointer) / 2
+        } else {
+            target
+        };
+        return Some((value, next, 4));
+    }
+    let next = (raw >> 51) & 0xFFF;
+    let value = match format {
+        DYLD_CHAINED_PTR_64 | DYLD_CHAINED_PTR_64_OFFSET => {
+            if raw >> 63 != 0 {
+                0
+            } else {
+                let target = raw & 0xF_FFFF_FFFF;
+                let high8 = (raw >> 36) & 0xFF;
+                let target = if format == DYLD_CHAINED_PTR_64_OFFSET {
+                    base + target
+                } else {
+                    target
+                };
+                (high8 << 56) | target
+            }
+        }
+        DYLD_CHAINED_PTR_64_KERNEL_CACHE | DYLD_CHAINED_PTR_X86_64_KERNEL_CACHE => {
+            base + (raw & 0x3FFF_FFFF)
+        }
+        _ => return None,
+    };
+    let stride = if format == DYLD_CHAINED_PTR_X86_64_KERNEL_CACHE {
+        1
+    } else {
+        4
+    };
+    Some((value, next, stride))
+}
+
+/// Rewrites every pointer covered by `LC_DYLD_CHAINED_FIXUPS` in place, so they can be read as plain addresses. Fails
+/// if the fixups can't be followed to the end, but not if there are none.
+fn apply_chained_fixups<Mach: MachHeader<Endian = Endianness>>(data: &mut [u8]) -> Option<()> {
+    let (fixups, segments) = {
+        let header = Mach::parse(&*data, 0).ok()?;
+        let endian = header.endian().ok()?;
+        let mut commands = header.load_commands(endian, &*data, 0).ok()?;
+        let mut fixups = None;
+        let mut segments = Vec::new();
+        while let Some(command) = commands.next().ok()? {
+            if let Some((segment, _)) = Mach::Segment::from_command(command).ok()? {
+                segments.push(Segment {
+                    vmaddr: segment.vmaddr(endian).into(),
+                    fileoff: segment.fileoff(endian).into(),
+                    filesize: segment.filesize(endian).into(),
+                });
+            } else if command.cmd() == LC_DYLD_CHAINED_FIXUPS {
+                let v: &LinkeditDataCommand<_> = command.data().ok()?;
+                let start = v.dataoff.get(endian) as usize;
+                fixups = Some(start..start + v.datasize.get(endian) as usize);
+            }
+        }
+        let Some(fixups) = fixups else {
+            return Some(());
+        };
+        (data.get(fixups)?.to_vec(), segments)
+    };
+    let base = segments
+        .iter()
+        .find(|v| v.fileoff == 0 && v.filesize != 0)?
+        .vmaddr;
+    let file_offset = |vmoff: u64| {
+        segments.iter().find_map(|v| {
+            let rel = (base + vmoff).checked_sub(v.vmaddr)?;
+            (rel < v.filesize).then(|| (v.fileoff + rel) as usize)
+        })
+    };
+
+    let starts = read_u32(&fixups, 4)?;
+    for i in 0..read_u32(&fixups, starts)? {
+        let info = read_u32(&fixups, starts + 4 + 4 * i)?;
+        if info == 0 {
+            continue;
+        }
+        let info = starts + info;
+        let page_size = u64::from(read_u16(&fixups, info + 4)?);
+        let format = read_u16(&fixups, info + 6)?;
+        let segment_offset = read_u64(&fixups, info + 8)?;
+        let max_valid_pointer = read_u32(&fixups, info + 16)? as u64;
+        let width = pointer_width(format);
+        for page in 0..read_u16(&fixups, info + 20)? as usize {
+            let start = read_u16(&fixups, info + 22 + 2 * page)?;
+            if start == DYLD_CHAINED_PTR_START_NONE {
+                continue;
+            }
+            let mut vmoff = segment_offset + page as u64 * page_size + u64::from(start);
+            loop {
+                let offset = file_offset(vmoff)?;
+                let slot = data.get_mut(offset..offset.checked_add(width)?)?;
+                let mut raw = [0; 8];
+                raw[..width].copy_from_slice(slot);
+                let (value, next, stride) =
+                    decode(format, u64::from_le_bytes(raw), base, max_valid_pointer)?;
+                slot.copy_from_slice(&value.to_le_bytes()[..width]);
+                if next == 0 {
+                    break;
+                }
+                vmoff += next * stride;
+            }
+        }
+    }
+    Some(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        error::Error,
+        fixtures::{self, descriptor_data, extractor, MACHO_DATA},
+        loader,
+        source::ByteSource,
+    };
+
+    #[test]
+    fn applies_chained_fixups() {
+        let kext = fixtures::macho(
+            true,
+            descriptor_data(0xC),
+            &[(0x30, Some(MACHO_DATA + 0x100)), (0x80, None)],
+            &[("Hawaii_pfp", 0x10)],
+        );
+        let image = loader::load(&kext, None).unwrap();
+        assert_eq!(image.pointer_width(), 8);
+        let descriptor = image.find_symbol("Hawaii_pfp").unwrap();
+        assert_eq!(descriptor, MACHO_DATA + 0x10);
+        // Binds to other kexts are left null.
+        assert_eq!(image.read_bytes(MACHO_DATA + 0x80, 8), [0; 8]);
+
+        let gc = extractor("GC");
+        let fw = gc.read_fw(&image, descriptor).unwrap();
+        assert_eq!(fw.fw_off, MACHO_DATA + 0x100);
+        assert_eq!(fw.data, (0..0x40).collect::<Vec<u8>>());
+        assert_eq!(fw.file_offset, Some(0x1100));
+    }
+
+    #[test]
+    fn loads_32_bit() {
+        let kext = fixtures::macho(
+            false,
+            descriptor_data(0x8),
+            &[(0x1C, Some(MACHO_DATA + 0x100))],
+            &[("Hawaii_sdma", 0x10)],
+        );
+        let image = loader::load(&kext, None).unwrap();
+        assert_eq!(image.pointer_width(), 4);
+        let descriptor = image.find_symbol("Hawaii_sdma").unwrap();
+        let sdma = extractor("SDMA");
+        let fw = sdma.read_fw(&image, descriptor).unwrap();
+        assert_eq!(fw.fw_off, MACHO_DATA + 0x100);
+        assert_eq!(fw.data, (0..0x40).collect::<Vec<u8>>());
+    }
+
+    #[test]
+    fn loads_without_fixups() {
+        let kext = fixtures::macho(true, descriptor_data(0xC), &[], &[("Hawaii_pfp", 0x10)]);
+        let image = loader::load(&kext, None).unwrap();
+        assert_eq!(image.find_symbol("Hawaii_pfp"), Some(MACHO_DATA + 0x10));
+    }
+
+    #[test]
+    fn malformed_fixups() {
+        let mut kext = fixtures::macho(
+            true,
+            descriptor_data(0xC),
+            &[(0x30, Some(MACHO_DATA + 0x100))],
+            &[],
+        );
+        // Point the chain past the end of `__DATA`.
+        kext[0x1000 + 0x30 + 7] |= 0x7F;
+        assert!(matches!(
+            loader::load(&kext, None),
+            Err(Error::MalformedChainedFixups)
+        ));
+    }
+}
@@ -0,0 +1,126 @@
+//! Copyright © 2024 ChefKiss Inc. Licensed under the Thou Shalt Not Profit License version 1.5.
+//! See LICENSE for details.
+
+mod elf;
+mod macho;
+mod pe;
+
+use object::{
+    macho::{FatArch32, FatArch64, MachHeader32, MachHeader64},
+    pe::{ImageNtHeaders32, ImageNtHeaders64},
+    BinaryFormat, FileKind, Object, ObjectKind, ObjectSection, ObjectSymbol, SectionKind,
+};
+
+use crate::{
+    error::{Error, Result},
+    image::{Image, SegmentKind},
+    source::Endianness,
+};
+
+pub use pe::load_pdb;
+
+/// Maps a driver binary into an `Image`, rebasing it to `base` where the format allows. Anything `object` does not
+/// understand is mapped flat at `base`, or 0. Fails if the pointers of the driver can't be resolved, e.g. over a malformed
+/// base relocation block.
+pub fn load(data: &[u8], base: Option<u64>) -> Result<Image> {
+    let thin = match FileKind::parse(data) {
+        Ok(FileKind::MachOFat32) => macho::thin::<FatArch32>(data),
+        Ok(FileKind::MachOFat64) => macho::thin::<FatArch64>(data),
+        _ => None,
+    };
+    if let Some((offset, data)) = thin {
+        let mut ret = load(data, base)?;
+        ret.shift_file_offsets(offset);
+        return Ok(ret);
+    }
+    let image = match FileKind::parse(data) {
+        Ok(FileKind::Pe32) => {
+            Some(pe::load::<ImageNtHeaders32>(data, base).map_err(Error::MalformedPe))
+        }
+        Ok(FileKind::Pe64) => {
+            Some(pe::load::<ImageNtHeaders64>(data, base).map_err(Error::MalformedPe))
+        }
+        Ok(FileKind::MachO32) => macho::load::<MachHeader32<_>>(data),
+        Ok(FileKind::MachO64) => macho::load::<MachHeader64<_>>(data),
+        _ => None,
+    };
+    if let Some(image) = image {
+        return image;
+    }
+    let Ok(file) = object::File::parse(data) else {
+        return Ok(Image::flat(
+            base.unwrap_or_default(),
+            data.to_vec(),
+            8,
+            Endianness::Little,
+        ));
+    };
+    Ok(match (file.format(), file.kind()) {
+        (BinaryFormat::Elf, ObjectKind::Relocatable) => elf::load(&file, base),
+        _ => load_linked(&file, ""),
+    })
+}
+
+fn pointer_width(file: &object::File) -> usize {
+    if file.is_64() {
+        8
+    } else {
+        4
+    }
+}
+
+fn endianness(file: &object::File) -> Endianness {
+    if file.is_little_endian() {
+        Endianness::Little
+    } else {
+        Endianness::Big
+    }
+}
+
+/// Whether a section has contents worth mapping.
+fn is_mapped(section: &object::Section) -> bool {
+    section.size() != 0 && !matches!(section.kind(), SectionKind::UninitializedData)
+}
+
+fn segment_kind(section: &object::Section) -> SegmentKind {
+    if section.kind() == SectionKind::Text {
+        SegmentKind::Code
+    } else {
+        SegmentKind::Data
+    }
+}
+
+/// Maps every section at the address it was linked at, removing `symbol_prefix` from symbol names.
+fn load_linked(file: &object::File, symbol_prefix: &str) -> Image {
+    let mut ret = Image::new(
+        file.relative_address_base(),
+        pointer_width(file),
+        endianness(file),
+    );
+    for section in file.sections() {
+        if !is_mapped(&section) {
+            continue;
+        }
+        let Ok(data) = section.data() else {
+            continue;
+        };
+        ret.add_segment(
+            section.address(),
+            data.to_vec(),
+            segment_kind(&section),
+            section.file_range().map(|(off, _)| off),
+        );
+    }
+    for sym in file.symbols() {
+        if sym.is_undefined() || sym.address() == 0 {
+            continue;
+        }
+        if let Ok(name) = sym.name() {
+            ret.add_symbol(
+                sym.address(),
+                name.strip_prefix(symbol_prefix).unwrap_or(name),
+            );
+        }
+    }
+    ret
+}
@@ -0,0 +1,283 @@
+//! Copyright © 2024 ChefKiss Inc. Licensed under the Thou Shalt Not Profit License version 1.5.
+//! See LICENSE for details.
+
+use std::{fs::File, path::Path};
+
+use object::{
+    pe::{IMAGE_REL_BASED_DIR64, IMAGE_REL_BASED_HIGHLOW, IMAGE_SCN_CNT_CODE},
+    read::pe::{ImageNtHeaders, PeFile},
+    LittleEndian as LE, Object, ObjectSymbol,
+};
+use pdb::FallibleIterator;
+
+use crate::{
+    image::{Image, SegmentKind},
+    source::Endianness,
+};
+
+struct Section {
+    rva: u32,
+    data: Vec<u8>,
+    kind: SegmentKind,
+    file_offset: u64,
+}
+
+impl Section {
+    fn slice_mut(&mut self, rva: u32, len: usize) -> Option<&mut [u8]> {
+        let start = rva.checked_sub(self.rva)? as usize;
+        self.data.get_mut(start..start.checked_add(len)?)
+    }
+}
+
+/// Maps the sections of a PE driver at `base`, or its preferred image base, applying base relocations when the two
+/// differ.
+pub fn load<Pe: ImageNtHeaders>(data: &[u8], base: Option<u64>) -> object::Result<Image> {
+    let file = PeFile::<Pe>::parse(data)?;
+    let image_base = file.relative_address_base();
+    let base = base.unwrap_or(image_base);
+    let delta = base.wrapping_sub(image_base);
+    let pointer_width = if file.is_64() { 8 } else { 4 };
+
+    let mut sections = Vec::new();
+    for header in file.section_table().iter() {
+        let (file_offset, size) = header.pe_file_range();
+        if size == 0 {
+            continue;
+        }
+        let Ok(data) = header.pe_data(data) else {
+            continue;
+        };
+        sections.push(Section {
+            rva: header.virtual_address.get(LE),
+            data: data.to_vec(),
+            kind: if header.characteristics.get(LE) & IMAGE_SCN_CNT_CODE != 0 {
+                SegmentKind::Code
+            } else {
+                SegmentKind::Data
+            },
+            file_offset: file_offset.into(),
+        });
+    }
+
+    if delta != 0 {
+        if let Some(blocks) = file
+            .data_directories()
+            .relocation_blocks(data, &file.section_table())?
+        {
+            for block in blocks {
+                for reloc in block? {
+                    relocate(&mut sections, reloc.virtual_address, reloc.typ, delta);
+                }
+            }
+        }
+    }
+
+    let mut ret = Image::new(base, pointer_width, Endianness::Little);
+    for section in sections {
+        ret.add_segment(
+            base + u64::from(section.rva),
+            section.data,
+            section.kind,
+            Some(section.file_offset),
+        );
+    }
+    // Like `Object::exports`, but skipping malformed entries instead of failing the whole image over them.
+    if let Ok(Some(exports)) = file.export_table() {
+        for (name_pointer, index) in exports.name_iter() {
+            let (Ok(name), Ok(rva)) = (
+                exports.name_from_pointer(name_pointer),
+                exports.address_by_index(index.into()),
+            ) else {
+                continue;
+            };
+            if !exports.is_forward(rva) {
+                ret.add_symbol(base + u64::from(rva), String::from_utf8_lossy(name));
+            }
+        }
+    }
+    for sym in file.symbols() {
+        if sym.is_undefined() || sym.address() == 0 {
+            continue;
+        }
+        if let Ok(name) = sym.name() {
+            ret.add_symbol(sym.address().wrapping_add(delta), name);
+        }
+    }
+    Ok(ret)
+}
+
+fn relocate(sections: &mut [Section], rva: u32, typ: u16, delta: u64) {
+    let width = match typ {
+        IMAGE_REL_BASED_HIGHLOW => 4,
+        IMAGE_REL_BASED_DIR64 => 8,
+        // Padding, or types that do not occur in x86 drivers.
+        _ => return,
+    };
+    // Relocations in the uninitialised tail of a section have nothing to patch.
+    let Some(slot) = sections.iter_mut().find_map(|v| v.slice_mut(rva, width)) else {
+        return;
+    };
+    if width == 4 {
+        let value = u32::from_le_bytes(slot.try_into().unwrap()).wrapping_add(delta as u32);
+        slot.copy_from_slice(&value.to_le_bytes());
+    } else {
+        let value = u64::from_le_bytes(slot.try_into().unwrap()).wrapping_add(delta);
+        slot.copy_from_slice(&value.to_le_bytes());
+    }
+}
+
+/// Adds the public and global data symbols of a matching PDB to a PE image. Returns how many were added.
+pub fn load_pdb(image: &mut Image, path: &Path) -> Result<usize, pdb::Error> {
+    let mut pdb = pdb::PDB::open(File::open(path)?)?;
+    let symbols = pdb.global_symbols()?;
+    let address_map = pdb.address_map()?;
+    let mut ret = 0;
+    let mut iter = symbols.iter();
+    while let Some(symbol) = iter.next()? {
+        let (offset, name) = match symbol.parse() {
+            Ok(pdb::SymbolData::Public(v)) => (v.offset, v.name),
+            Ok(pdb::SymbolData::Data(v)) => (v.offset, v.name),
+            _ => continue,
+        };
+        let Some(rva) = offset.to_rva(&address_map) else {
+            continue;
+        };
+        image.add_symbol(image.base() + u64::from(rva.0), name.to_string());
+        ret += 1;
+    }
+    Ok(ret)
+}
+
+#[cfg(test)]
+mod tests {
+    use object::pe::ImageNtHeaders64;
+
+    use super::*;
+    use crate::{
+        error::Error,
+        fixtures::{
+            self, extractor, pe_rva, IMAGE_DIRECTORY_ENTRY_BASERELOC, IMAGE_DIRECTORY_ENTRY_EXPORT,
+            PE_FILE_ALIGNMENT, PE_HEADERS_SIZE,
+        },
+        source::ByteSource,
+    };
+
+    const IMAGE_BASE: u64 = 0x1_4000_0000;
+    const DATA: u64 = IMAGE_BASE + pe_rva(1) as u64;
+
+    /// A driver with a GC descriptor at the start of `.data` pointing 0x100 bytes in, exported as `_Hawaii_pfp`
+    /// alongside an export whose name is out of bounds.
+    fn driver() -> Vec<u8> {
+        let mut data = vec![0; 0x200];
+        data[0xC..0x10].copy_from_slice(&0x40u32.to_le_bytes());
+        data[0x20..0x28].copy_from_slice(&(DATA + 0x100).to_le_bytes());
+        for (i, v) in data[0x100..0x140].iter_mut().enumerate() {
+            *v = i as u8;
+        }
+
+        let edata = pe_rva(2);
+        let mut exports = vec![0; 0x100];
+        for (offset, v) in [
+            (0x10, 1),
+            (0x14, 2),
+            (0x18, 2),
+            (0x1C, edata + 0x40),
+            (0x20, edata + 0x50),
+            (0x24, edata + 0x60),
+            (0x40, pe_rva(1)),
+            (0x44, pe_rva(1) + 0x100),
+            (0x50, edata + 0x70),
+            (0x54, 0x7FFF_0000),
+        ] {
+            exports[offset..offset + 4].copy_from_slice(&u32::to_le_bytes(v));
+        }
+        exports[0x62..0x64].copy_from_slice(&1u16.to_le_bytes());
+        exports[0x70..0x7C].copy_from_slice(b"_Hawaii_pfp\0");
+
+        let relocations = fixtures::pe_relocations(pe_rva(1), &[0x20]);
+        fixtures::pe64(
+            IMAGE_BASE,
+            &[
+                (".text", vec![0xC3; 0x10]),
+                (".data", data),
+                (".edata", exports),
+                (".reloc", relocations.clone()),
+            ],
+            &[
+                (IMAGE_DIRECTORY_ENTRY_EXPORT, edata, 0x100),
+                (
+                    IMAGE_DIRECTORY_ENTRY_BASERELOC,
+                    pe_rva(3),
+                    relocations.len() as u32,
+                ),
+            ],
+        )
+    }
+
+    #[test]
+    fn maps_sections() {
+        let data = driver();
+        let image = load::<ImageNtHeaders64>(&data, None).unwrap();
+        assert_eq!(image.base(), IMAGE_BASE);
+        let kinds: Vec<_> = image
+            .segments()
+            .iter()
+            .map(|v| (v.address, v.kind))
+            .collect();
+        assert_eq!(
+            kinds[..2],
+            [
+                (IMAGE_BASE + 0x1000, SegmentKind::Code),
+                (DATA, SegmentKind::Data)
+            ]
+        );
+        assert_eq!(image.file_offset(DATA + 0x100), Some(0x600 + 0x100));
+        assert_eq!(image.find_symbol("_Hawaii_pfp"), Some(DATA));
+
+        let fw = extractor("GC").read_fw(&image, DATA).unwrap();
+        assert_eq!(fw.name, "Hawaii_pfp");
+        assert_eq!(fw.fw_off, DATA + 0x100);
+        assert_eq!(fw.data, (0..0x40).collect::<Vec<u8>>());
+    }
+
+    #[test]
+    fn rebases() {
+        let data = driver();
+        let base = 0xFFFF_F800_0000_0000;
+        let image = load::<ImageNtHeaders64>(&data, Some(base)).unwrap();
+        let descriptor = base + pe_rva(1) as u64;
+        assert_eq!(image.find_symbol("_Hawaii_pfp"), Some(descriptor));
+        assert_eq!(
+            extractor("GC").read_fw_info(&image, descriptor).unwrap(),
+            (descriptor + 0x100, 0x40)
+        );
+    }
+
+    #[test]
+    fn skips_bad_exports() {
+        // The second export's name is out of bounds, which must not lose the first one, or the whole image to
+        // `loader::load`'s fallback, which can't rebase.
+        let data = driver();
+        let image = crate::loader::load(&data, Some(0x1000_0000)).unwrap();
+        assert_eq!(image.base(), 0x1000_0000);
+        assert_eq!(
+            image.find_symbol("_Hawaii_pfp"),
+            Some(0x1000_0000 + pe_rva(1) as u64)
+        );
+    }
+
+    #[test]
+    fn fails_on_bad_relocations() {
+        // A block size below its own header can't be walked, so `base` can't be honoured, and a flat fallback would
+        // silently ignore it.
+        let mut data = driver();
+        let block = PE_HEADERS_SIZE + 3 * PE_FILE_ALIGNMENT;
+        data[block + 4..block + 8].copy_from_slice(&2u32.to_le_bytes());
+        assert!(matches!(
+            crate::loader::load(&data, Some(0x1000_0000)),
+            Err(Error::MalformedPe(_))
+        ));
+        // Without rebasing, the relocations are never read.
+        assert!(crate::loader::load(&data, None).is_ok());
+    }
+}
@@ -0,0 +1,475 @@
+//! Copyright © 2024 ChefKiss Inc. Licensed under the Thou Shalt Not Profit License version 1.5.
+//! See LICENSE for details.
+
+use std::{
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use amd_catalyst_fw_extractor::{
+    batch, diff, dump,
+    firmware::{Extractor, OutputFormat},
+    image::Image,
+    infer::{self, Pattern},
+    layout::Layout,
+    loader,
+    manifest::Manifest,
+    patch, scan, store,
+};
+use clap::{Args, Parser, Subcommand};
+
+#[derive(Parser)]
+#[command(version, about = "Extracts firmware from AMD Catalyst driver images")]
+struct Cli {
+    /// TOML file with additional or overriding descriptor layouts
+    #[arg(long, global = true)]
+    layouts: Option<PathBuf>,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Args)]
+struct Driver {
+    /// Driver image (e.g. atikmdag.sys, fglrx.ko, AMDRadeonX4000)
+    #[arg(value_name = "DRIVER")]
+    path: PathBuf,
+    /// Load address. Rebases PE images, and places images that are not a recognised executable format
+    #[arg(long, value_parser = parse_addr)]
+    base: Option<u64>,
+    /// PDB to read symbols from, defaults to a `.pdb` next to a PE driver
+    #[arg(long)]
+    pdb: Option<PathBuf>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Extract the firmware referenced by a single descriptor
+    Extract {
+        #[command(flatten)]
+        driver: Driver,
+        /// Descriptor address (hex, `0x` prefixed) or symbol name
+        descriptor: String,
+        /// Descriptor layout name (e.g. `gc` or `sdma`), inferred from the symbol name and contents if omitted
+        #[arg(short = 't', long, visible_alias = "type")]
+        layout: Option<String>,
+        /// Output file, defaults to `<name>.bin` in the current directory
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// `raw` ucode or `amdgpu` header-wrapped
+        #[arg(short, long, default_value = "raw")]
+        format: OutputFormat,
+    },
+    /// Replace the firmware referenced by a descriptor and save a patched copy of the driver
+    Patch {
+        #[command(flatten)]
+        driver: Driver,
+        /// Descriptor address (hex, `0x` prefixed) or symbol name
+        descriptor: String,
+        /// Replacement raw ucode
+        blob: PathBuf,
+        /// Descriptor layout name, inferred from the symbol name and contents if omitted
+        #[arg(short = 't', long, visible_alias = "type")]
+        layout: Option<String>,
+        /// Output file, defaults to `<driver>.patched.<ext>` next to the driver
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Extract every discovered firmware blob into a directory
+    ExtractAll {
+        #[command(flatten)]
+        driver: Driver,
+        /// Output directory, created if missing
+        output: PathBuf,
+        #[arg(short, long, default_value = "raw")]
+        format: OutputFormat,
+        #[arg(long, default_value_t = 4)]
+        min_score: u32,
+    },
+    /// Add every discovered firmware blob to a content-addressed store, skipping blobs already in it
+    Store {
+        #[command(flatten)]
+        driver: Driver,
+        /// Store directory, created if missing
+        store: PathBuf,
+        #[arg(short, long, default_value = "raw")]
+        format: OutputFormat,
+        #[arg(long, default_value_t = 4)]
+        min_score: u32,
+    },
+    /// List the drivers in a store that ship a blob
+    Which {
+        /// Store directory
+        store: PathBuf,
+        /// SHA-256 of the blob, or a prefix of at least 4 hex digits of it
+        hash: String,
+    },
+    /// List extracted ucode dword by dword, with its jump table
+    Dump {
+        /// Raw ucode, as written by `extract`
+        path: PathBuf,
+        /// Separately stored jump table, defaults to a `_jt.bin` next to the ucode
+        #[arg(long, conflicts_with = "jt_offset")]
+        jt: Option<PathBuf>,
+        /// Jump table offset into the ucode, as a hex number of dwords
+        #[arg(long, value_parser = parse_addr, requires = "jt_size")]
+        jt_offset: Option<u64>,
+        /// Jump table size, as a hex number of dwords
+        #[arg(long, value_parser = parse_addr, requires = "jt_offset")]
+        jt_size: Option<u64>,
+    },
+    /// Compare the firmware of two drivers
+    Diff {
+        /// Older driver image
+        old: PathBuf,
+        /// Newer driver image
+        new: PathBuf,
+        #[arg(long, default_value_t = 4)]
+        min_score: u32,
+    },
+    /// List every discovered firmware blob with its ASIC and versions
+    List {
+        #[command(flatten)]
+        driver: Driver,
+        #[arg(long, default_value_t = 4)]
+        min_score: u32,
+    },
+    /// List every location that looks like a firmware descriptor
+    Scan {
+        #[command(flatten)]
+        driver: Driver,
+        /// Hide candidates scoring below this
+        #[arg(long, default_value_t = 4)]
+        min_score: u32,
+    },
+}
+
+fn parse_addr(s: &str) -> Result<u64, String> {
+    let s = s
+        .strip_prefix("0x")
+        .or_else(|| s.strip_prefix("0X"))
+        .unwrap_or(s);
+    u64::from_str_radix(s, 16).map_err(|e| e.to_string())
+}
+
+fn resolve_descriptor(image: &Image, descriptor: &str) -> Option<u64> {
+    image
+        .find_symbol(descriptor)
+        .or_else(|| parse_addr(descriptor).ok())
+}
+
+/// The layout named `layout`, or the one inferred for the descriptor at `addr`.
+fn resolve_layout<'a>(
+    image: &Image,
+    layouts: &'a [Layout],
+    patterns: &[Pattern],
+    addr: u64,
+    layout: Option<&str>,
+) -> Result<&'a Layout, String> {
+    match layout {
+        Some(layout) => {
+            Layout::find(layouts, layout).ok_or_else(|| format!("Unknown layout `{layout}`"))
+        }
+        None => {
+            let layout = infer::infer(image, layouts, patterns, addr).map_err(|e| e.to_string())?;
+            eprintln!("Using the {} layout", layout.name);
+            Ok(layout)
+        }
+    }
+}
+
+/// Maps the driver, returning it along with the file contents, so they needn't be read again.
+fn load_driver(driver: &Driver) -> Result<(Image, Vec<u8>), String> {
+    let path = &driver.path;
+    let data =
+        std::fs::read(path).map_err(|e| format!("Failed to read {}: {e}", path.display()))?;
+    let mut image = loader::load(&data, driver.base)
+        .map_err(|e| format!("Failed to load {}: {e}", path.display()))?;
+    let pdb = match &driver.pdb {
+        Some(v) => Some(v.clone()),
+        None => Some(path.with_extension("pdb")).filter(|v| v.exists()),
+    };
+    if let Some(pdb) = pdb {
+        let count = loader::load_pdb(&mut image, &pdb)
+            .map_err(|e| format!("Failed to read {}: {e}", pdb.display()))?;
+        eprintln!("Loaded {count} symbols from {}", pdb.display());
+    }
+    Ok((image, data))
+}
+
+/// Starts a manifest for blobs extracted from `driver`, whose contents are `data`.
+fn manifest(driver: &Driver, data: &[u8]) -> Manifest {
+    Manifest::with_data(
+        &driver
+            .path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy(),
+        data,
+    )
+}
+
+fn extract(
+    layouts: &[Layout],
+    patterns: &[Pattern],
+    driver: &Driver,
+    descriptor: &str,
+    layout: Option<&str>,
+    output: Option<PathBuf>,
+    format: OutputFormat,
+) -> Result<(), String> {
+    let (image, data) = load_driver(driver)?;
+    let addr = resolve_descriptor(&image, descriptor)
+        .ok_or_else(|| format!("`{descriptor}` is neither a symbol nor an address"))?;
+    let layout = resolve_layout(&image, layouts, patterns, addr, layout)?;
+    let fw = Extractor::new(layout.clone())
+        .read_fw(&image, addr)
+        .map_err(|e| e.to_string())?;
+    let path = match output {
+        Some(v) => v,
+        None => {
+            let path = PathBuf::from(format.file_name(&fw));
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)
+                    .map_err(|e| format!("Failed to create {}: {e}", parent.display()))?;
+            }
+            path
+        }
+    };
+    let paths = format.write(&fw, &path).map_err(|e| e.to_string())?;
+    let mut manifest = manifest(driver, &data);
+    manifest.push(&fw, &paths, path.parent().unwrap_or(Path::new("")));
+    manifest
+        .write(&path.with_extension("json"))
+        .map_err(|e| e.to_string())?;
+    for path in paths {
+        println!("{} -> {}", fw.name, path.display());
+    }
+    Ok(())
+}
+
+fn patch(
+    layouts: &[Layout],
+    patterns: &[Pattern],
+    driver: &Driver,
+    descriptor: &str,
+    blob: &Path,
+    layout: Option<&str>,
+    output: Option<PathBuf>,
+) -> Result<(), String> {
+    let read = |path: &Path| {
+        std::fs::read(path).map_err(|e| format!("Failed to read {}: {e}", path.display()))
+    };
+    let (image, data) = load_driver(driver)?;
+    let addr = resolve_descriptor(&image, descriptor)
+        .ok_or_else(|| format!("`{descriptor}` is neither a symbol nor an address"))?;
+    let layout = resolve_layout(&image, layouts, patterns, addr, layout)?;
+    let patched = patch::inject(
+        &image,
+        &data,
+        &Extractor::new(layout.clone()),
+        addr,
+        &read(blob)?,
+    )
+    .map_err(|e| e.to_string())?;
+    let output = output.unwrap_or_else(|| {
+        let path = &driver.path;
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        match path.extension() {
+            Some(ext) => path.with_file_name(format!("{stem}.patched.{}", ext.to_string_lossy())),
+            None => path.with_file_name(format!("{stem}.patched")),
+        }
+    });
+    std::fs::write(&output, &patched.data)
+        .map_err(|e| format!("Failed to write {}: {e}", output.display()))?;
+    if let Some(rva) = patched.section_rva {
+        eprintln!("Moved the firmware to a new section at RVA {rva:#X}");
+    }
+    println!("{:#X} -> {}", patched.address, output.display());
+    Ok(())
+}
+
+fn extract_all(
+    layouts: &[Layout],
+    driver: &Driver,
+    output: PathBuf,
+    format: OutputFormat,
+    min_score: u32,
+) -> Result<(), String> {
+    let (image, data) = load_driver(driver)?;
+    let blobs = batch::collect(&image, layouts, min_score);
+    std::fs::create_dir_all(&output)
+        .map_err(|e| format!("Failed to create {}: {e}", output.display()))?;
+    let paths = batch::write_all(&blobs, &output, format, manifest(driver, &data))
+        .map_err(|e| e.to_string())?;
+    for path in paths {
+        println!("{}", path.display());
+    }
+    Ok(())
+}
+
+fn add_to_store(
+    layouts: &[Layout],
+    driver: &Driver,
+    dir: PathBuf,
+    format: OutputFormat,
+    min_score: u32,
+) -> Result<(), String> {
+    let (image, data) = load_driver(driver)?;
+    let blobs = batch::collect(&image, layouts, min_score);
+    let stored =
+        store::add(&blobs, &dir, format, manifest(driver, &data)).map_err(|e| e.to_string())?;
+    println!(
+        "{} blobs, {} files, {} new -> {}",
+        blobs.len(),
+        stored.objects,
+        stored.new_objects,
+        stored.index.display()
+    );
+    Ok(())
+}
+
+fn which(dir: PathBuf, hash: &str) -> Result<(), String> {
+    let hits = store::find(&dir, hash).map_err(|e| e.to_string())?;
+    if hits.is_empty() {
+        return Err(format!("No driver in {} ships {hash}", dir.display()));
+    }
+    print!("{}", store::report(&hits));
+    Ok(())
+}
+
+fn dump(path: &Path, jt: Option<PathBuf>, jt_range: Option<(u64, u64)>) -> Result<(), String> {
+    let read = |path: &Path| {
+        std::fs::read(path).map_err(|e| format!("Failed to read {}: {e}", path.display()))
+    };
+    let mut data = read(path)?;
+    let jt = jt.or_else(|| {
+        let stem = path.file_stem()?.to_string_lossy();
+        Some(path.with_file_name(format!("{stem}_jt.bin"))).filter(|v| v.exists())
+    });
+    let jt = match (jt, jt_range) {
+        (_, Some((offset, size))) => {
+            let range = offset
+                .checked_add(size)
+                .and_then(|end| Some(usize::try_from(offset).ok()?..usize::try_from(end).ok()?))
+                .ok_or_else(|| {
+                    format!("{size:#x} dwords at {offset:#x} overflow the address space")
+                })?;
+            Some(range)
+        }
+        (Some(jt), None) => {
+            let start = data.len() / 4;
+            data.extend(read(&jt)?);
+            Some(start..data.len() / 4)
+        }
+        (None, None) => None,
+    };
+    print!("{}", dump::listing(&data, jt));
+    Ok(())
+}
+
+fn diff(layouts: &[Layout], old: PathBuf, new: PathBuf, min_score: u32) -> Result<(), String> {
+    let collect = |path| {
+        let (image, _) = load_driver(&Driver {
+            path,
+            base: None,
+            pdb: None,
+        })?;
+        Ok::<_, String>(batch::collect(&image, layouts, min_score))
+    };
+    let old = collect(old)?;
+    let new = collect(new)?;
+    print!("{}", diff::report(&diff::diff(&old, &new)));
+    Ok(())
+}
+
+fn list(layouts: &[Layout], driver: &Driver, min_score: u32) -> Result<(), String> {
+    let (image, _) = load_driver(driver)?;
+    print!(
+        "{}",
+        batch::report(&batch::collect(&image, layouts, min_score))
+    );
+    Ok(())
+}
+
+fn scan(layouts: &[Layout], driver: &Driver, min_score: u32) -> Result<(), String> {
+    let (image, _) = load_driver(driver)?;
+    print!("{}", scan::report(&scan::scan(&image, layouts, min_score)));
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let config = match &cli.layouts {
+        Some(path) => Layout::load(path).and_then(|v| Ok((v, Pattern::load(path)?))),
+        None => Ok((Layout::builtin(), Pattern::builtin())),
+    };
+    let res = config.and_then(|(layouts, patterns)| run(&layouts, &patterns, cli.command));
+    match res {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(layouts: &[Layout], patterns: &[Pattern], command: Command) -> Result<(), String> {
+    match command {
+        Command::Extract {
+            driver,
+            descriptor,
+            layout,
+            output,
+            format,
+        } => extract(
+            layouts,
+            patterns,
+            &driver,
+            &descriptor,
+            layout.as_deref(),
+            output,
+            format,
+        ),
+        Command::Patch {
+            driver,
+            descriptor,
+            blob,
+            layout,
+            output,
+        } => patch(
+            layouts,
+            patterns,
+            &driver,
+            &descriptor,
+            &blob,
+            layout.as_deref(),
+            output,
+        ),
+        Command::ExtractAll {
+            driver,
+            output,
+            format,
+            min_score,
+        } => extract_all(layouts, &driver, output, format, min_score),
+        Command::Store {
+            driver,
+            store,
+            format,
+            min_score,
+        } => add_to_store(layouts, &driver, store, format, min_score),
+        Command::Which { store, hash } => which(store, &hash),
+        Command::Dump {
+            path,
+            jt,
+            jt_offset,
+            jt_size,
+        } => dump(&path, jt, jt_offset.zip(jt_size)),
+        Command::Diff {
+            old,
+            new,
+            min_score,
+        } => diff(layouts, old, new, min_score),
+        Command::List { driver, min_score } => list(layouts, &driver, min_score),
+        Command::Scan { driver, min_score } => scan(layouts, &driver, min_score),
+    }
+}
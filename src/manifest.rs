@@ -0,0 +1,160 @@
+//! Copyright © 2024 ChefKiss Inc. Licensed under the Thou Shalt Not Profit License version 1.5.
+//! See LICENSE for details.
+
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    error::Error,
+    firmware::{Firmware, FirmwareType},
+};
+
+pub const MANIFEST_FILE_NAME: &str = "manifest.json";
+
+pub fn sha256_hex(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    pub name: String,
+    pub layout: String,
+    #[serde(rename = "type")]
+    pub ty: FirmwareType,
+    pub descriptor_address: u64,
+    pub virtual_address: u64,
+    pub file_offset: Option<u64>,
+    pub size: usize,
+    pub sha256: String,
+    pub crc32: u32,
+    pub ucode_version: u32,
+    pub feature_version: u32,
+    pub asic: Option<String>,
+    pub block: Option<String>,
+    /// Names of the files written for this blob, relative to the manifest.
+    pub files: Vec<String>,
+}
+
+impl Entry {
+    /// Describes `fw`, written to `files`, for a manifest in `dir`.
+    pub fn new(fw: &Firmware, files: &[PathBuf], dir: &Path) -> Self {
+        Self {
+            name: fw.name.clone(),
+            layout: fw.layout.clone(),
+            ty: fw.ty,
+            descriptor_address: fw.address,
+            virtual_address: fw.fw_off,
+            file_offset: fw.file_offset,
+            size: fw.data.len(),
+            sha256: sha256_hex(&fw.data),
+            crc32: crc32fast::hash(&fw.data),
+            ucode_version: fw.info.ucode_version,
+            feature_version: fw.info.feature_version,
+            asic: fw.class.as_ref().map(|v| v.asic.name.to_owned()),
+            block: fw.class.as_ref().map(|v| v.block.clone()),
+            files: files
+                .iter()
+                .map(|v| {
+                    v.strip_prefix(dir)
+                        .unwrap_or(v)
+                        .to_string_lossy()
+                        .replace('\\', "/")
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Provenance of a set of extracted blobs.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Manifest {
+    pub driver: Option<String>,
+    pub driver_sha256: Option<String>,
+    pub firmware: Vec<Entry>,
+}
+
+impl Manifest {
+    /// Starts a manifest for blobs extracted from a driver already in memory, named `driver`.
+    pub fn with_data(driver: &str, data: &[u8]) -> Self {
+        Self {
+            driver: Some(driver.to_owned()),
+            driver_sha256: Some(sha256_hex(data)),
+            firmware: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, fw: &Firmware, files: &[PathBuf], dir: &Path) {
+        self.firmware.push(Entry::new(fw, files, dir));
+    }
+
+    pub fn read(path: &Path) -> Result<Self, Error> {
+        Ok(serde_json::from_reader(BufReader::new(File::open(path)?)).map_err(io::Error::from)?)
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), Error> {
+        let mut w = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(&mut w, self).map_err(io::Error::from)?;
+        w.write_all(b"\n")?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    #[test]
+    fn describes_blobs() {
+        let mut manifest = Manifest::with_data("atikmdag.sys", b"driver");
+        assert_eq!(manifest.driver.as_deref(), Some("atikmdag.sys"));
+        assert_eq!(manifest.driver_sha256, Some(sha256_hex(b"driver")));
+
+        let dir = fixtures::temp_dir("manifest");
+        let mut fw = fixtures::firmware("Hawaii_mec", 0x1000, vec![1, 2, 3, 4]);
+        fw.file_offset = Some(0x600);
+        fw.info.ucode_version = 0x1A;
+        let files = [
+            dir.join("radeon/hawaii_mec.bin"),
+            dir.join("hawaii_mec_jt.bin"),
+        ];
+        manifest.push(&fw, &files, &dir);
+
+        let path = dir.join(MANIFEST_FILE_NAME);
+        manifest.write(&path).unwrap();
+        let read = Manifest::read(&path).unwrap();
+        std::fs::remove_dir_all(dir).unwrap();
+
+        let entry = &read.firmware[0];
+        assert_eq!(read.driver, manifest.driver);
+        assert_eq!(
+            (entry.name.as_str(), entry.layout.as_str()),
+            ("Hawaii_mec", "GC")
+        );
+        assert_eq!(entry.ty, FirmwareType::Gc);
+        assert_eq!(
+            (
+                entry.descriptor_address,
+                entry.virtual_address,
+                entry.file_offset
+            ),
+            (0x1000, 0x1100, Some(0x600))
+        );
+        assert_eq!(entry.size, 4);
+        assert_eq!(entry.sha256, sha256_hex(&[1, 2, 3, 4]));
+        assert_eq!(entry.crc32, crc32fast::hash(&[1, 2, 3, 4]));
+        assert_eq!((entry.ucode_version, entry.feature_version), (0x1A, 0));
+        assert_eq!(
+            (entry.asic.as_deref(), entry.block.as_deref()),
+            (Some("hawaii"), Some("mec"))
+        );
+        // Relative to the manifest, with forward slashes.
+        assert_eq!(entry.files, ["radeon/hawaii_mec.bin", "hawaii_mec_jt.bin"]);
+    }
+}
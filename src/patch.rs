@@ -0,0 +1,391 @@
+//! Copyright © 2024 ChefKiss Inc. Licensed under the Thou Shalt Not Profit License version 1.5.
+//! See LICENSE for details.
+
+use crate::{
+    error::{Error, Result},
+    firmware::Extractor,
+    layout::Field,
+    source::{ByteSource, Endianness, SymbolSource},
+};
+
+/// Name of the section replacement blobs too large for the original go to.
+pub const SECTION_NAME: &[u8; 8] = b".fwpatch";
+
+#[derive(Debug, Clone)]
+pub struct Patched {
+    /// The patched driver file.
+    pub data: Vec<u8>,
+    /// Address of the descriptor that was patched.
+    pub address: u64,
+    /// RVA of the new section, if the blob had to be moved there.
+    pub section_rva: Option<u32>,
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    Endianness::Little.read_u16(data.get(offset..offset + 2)?)
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Endianness::Little.read_u32(data.get(offset..offset + 4)?)
+}
+
+fn write(data: &mut [u8], offset: u64, bytes: &[u8]) -> Option<()> {
+    let start = usize::try_from(offset).ok()?;
+    data.get_mut(start..start.checked_add(bytes.len())?)?
+        .copy_from_slice(bytes);
+    Some(())
+}
+
+/// Rounds `value` up to `alignment`, which malformed headers can make zero or too large to round to.
+fn align_up(value: u32, alignment: u32) -> Result<u32> {
+    value
+        .checked_next_multiple_of(alignment)
+        .ok_or(Error::NoRoomForSection)
+}
+
+/// Offset of the PE signature, if `data` is a PE image.
+fn pe_header(data: &[u8]) -> Option<usize> {
+    if !data.starts_with(b"MZ") {
+        return None;
+    }
+    let offset = read_u32(data, 0x3C)? as usize;
+    (data.get(offset..offset + 4)? == b"PE\0\0").then_some(offset)
+}
+
+/// The PE image checksum, as Windows verifies it for drivers.
+fn pe_checksum(data: &[u8], checksum_offset: usize) -> u32 {
+    let mut sum = 0u64;
+    for (i, chunk) in data.chunks(2).enumerate() {
+        if (checksum_offset..checksum_offset + 4).contains(&(i * 2)) {
+            continue;
+        }
+        sum += u64::from(chunk[0]) | chunk.get(1).map_or(0, |v| u64::from(*v) << 8);
+        sum = (sum & 0xFFFF) + (sum >> 16);
+    }
+    sum = (sum & 0xFFFF) + (sum >> 16);
+    (sum + data.len() as u64) as u32
+}
+
+/// Appends a section holding `contents` to the PE image in `data`, returning its RVA and the image base pointers to it
+/// are relative to. The Authenticode signature is dropped, as patching invalidates it anyway, cutting the certificates
+/// off the end of the file so that the section takes their place. The checksum is left to the caller.
+fn append_pe_section(data: &mut Vec<u8>, contents: &[u8]) -> Result<(u32, u64)> {
+    let no_room = || Error::NoRoomForSection;
+    let nt = pe_header(data).ok_or_else(no_room)?;
+    let file_header = nt + 4;
+    let optional = file_header + 20;
+    let sections = read_u16(data, file_header + 2).ok_or_else(no_room)? as usize;
+    let optional_size = read_u16(data, file_header + 16).ok_or_else(no_room)? as usize;
+    let pe64 = read_u16(data, optional).ok_or_else(no_room)? == 0x20B;
+    let image_base = if pe64 {
+        Endianness::Little.read_u64(data.get(optional + 24..optional + 32).ok_or_else(no_room)?)
+    } else {
+        read_u32(data, optional + 28).map(u64::from)
+    }
+    .ok_or_else(no_room)?;
+    let section_alignment = read_u32(data, optional + 32).ok_or_else(no_room)?;
+    let file_alignment = read_u32(data, optional + 36).ok_or_else(no_room)?;
+    let headers_size = read_u32(data, optional + 60).ok_or_else(no_room)? as usize;
+    let table = optional + optional_size;
+    // The security directory holds a file offset rather than an RVA.
+    let security = optional + if pe64 { 112 } else { 96 } + 4 * 8;
+    let certificates = (security + 8 <= table)
+        .then(|| read_u32(data, security))
+        .flatten()
+        .filter(|v| *v != 0);
+
+    let mut end_rva = 0u32;
+    let mut end_raw = headers_size;
+    let mut first_raw = headers_size;
+    for i in 0..sections {
+        let header = table + i * 40;
+        let virtual_size = read_u32(data, header + 8).ok_or_else(no_room)?;
+        let rva = read_u32(data, header + 12).ok_or_else(no_room)?;
+        let raw_size = read_u32(data, header + 16).ok_or_else(no_room)?;
+        let raw = read_u32(data, header + 20).ok_or_else(no_room)?;
+        let end = rva
+            .checked_add(virtual_size.max(raw_size))
+            .ok_or_else(no_room)?;
+        end_rva = end_rva.max(end);
+        if raw_size != 0 {
+            first_raw = first_raw.min(raw as usize);
+            end_raw = end_raw.max(raw as usize + raw_size as usize);
+        }
+    }
+    let header = table + sections * 40;
+    let free = data
+        .get(header..header + 40)
+        .is_some_and(|v| v.iter().all(|v| *v == 0));
+    if header + 40 > headers_size.min(first_raw) || !free {
+        return Err(Error::NoRoomForSection);
+    }
+
+    // Only certificates past every section are cut off, as they are in signed drivers.
+    if let Some(offset) = certificates.map(|v| v as usize) {
+        if (end_raw..data.len()).contains(&offset) {
+            data.truncate(offset);
+        }
+    }
+    if security + 8 <= table {
+        data[security..security + 8].fill(0);
+    }
+
+    let size = u32::try_from(contents.len()).map_err(|_| no_room())?;
+    let rva = align_up(end_rva, section_alignment)?;
+    let raw = align_up(
+        u32::try_from(data.len()).map_err(|_| no_room())?,
+        file_alignment,
+    )?;
+    let raw_size = align_up(size, file_alignment)?;
+    let raw_end = raw.checked_add(raw_size).ok_or_else(no_room)?;
+    let image_size = align_up(
+        rva.checked_add(size).ok_or_else(no_room)?,
+        section_alignment,
+    )?;
+    data.resize(raw as usize, 0);
+    data.extend_from_slice(contents);
+    data.resize(raw_end as usize, 0);
+
+    let mut section = Vec::with_capacity(40);
+    section.extend_from_slice(SECTION_NAME);
+    for v in [size, rva, raw_size, raw, 0, 0] {
+        section.extend_from_slice(&v.to_le_bytes());
+    }
+    section.extend_from_slice(&[0; 4]);
+    // IMAGE_SCN_CNT_INITIALIZED_DATA | IMAGE_SCN_MEM_READ
+    section.extend_from_slice(&0x4000_0040u32.to_le_bytes());
+    data[header..header + 40].copy_from_slice(&section);
+    data[file_header + 2..file_header + 4].copy_from_slice(&(sections as u16 + 1).to_le_bytes());
+    data[optional + 56..optional + 60].copy_from_slice(&image_size.to_le_bytes());
+    Ok((rva, image_base))
+}
+
+/// Replaces the blob the descriptor at `offset` points to with `blob` in `driver`, the file `src` was loaded from, and
+/// updates the size field. Blobs larger than the original are moved to a new section in PE drivers.
+pub fn inject<S: ByteSource + SymbolSource + ?Sized>(
+    src: &S,
+    driver: &[u8],
+    extractor: &Extractor,
+    offset: u64,
+    blob: &[u8],
+) -> Result<Patched> {
+    extractor.check_fw(src, offset)?;
+    let address = Extractor::fw_info_addr(src, offset);
+    let (fw_off, fw_size) = extractor.read_fw_info(src, address)?;
+    let layout = extractor.layout();
+    let pointer_width = src.pointer_width();
+    let endianness = src.endianness();
+    let new_size = u32::try_from(blob.len()).map_err(|_| Error::SizeOverflow {
+        fw_off,
+        fw_size: u32::MAX,
+    })?;
+
+    let field_offset = |field: &Field| {
+        let field_address = address + field.offset(pointer_width);
+        src.file_offset(field_address).ok_or(Error::NotFileBacked {
+            address: field_address,
+        })
+    };
+    let size_offset = field_offset(&layout.size)?;
+    let size_width = layout.size.width(4);
+    let size_bytes =
+        endianness
+            .write_uint(new_size.into(), size_width)
+            .ok_or(Error::SizeOverflow {
+                fw_off,
+                fw_size: new_size,
+            })?;
+
+    let mut data = driver.to_vec();
+    let mut section_rva = None;
+    if new_size <= fw_size {
+        let start = src
+            .file_offset(fw_off)
+            .ok_or(Error::NotFileBacked { address: fw_off })?;
+        let last = fw_off + u64::from(fw_size.max(1)) - 1;
+        if src.file_offset(last) != Some(start + last - fw_off) {
+            return Err(Error::NotFileBacked { address: last });
+        }
+        let mut contents = blob.to_vec();
+        contents.resize(fw_size as usize, 0);
+        write(&mut data, start, &contents).ok_or(Error::NotFileBacked { address: fw_off })?;
+    } else {
+        if pe_header(driver).is_none() {
+            return Err(Error::BlobTooLarge {
+                address,
+                size: blob.len(),
+                capacity: fw_size,
+            });
+        }
+        let pointer_offset = field_offset(&layout.pointer)?;
+        let (rva, image_base) = append_pe_section(&mut data, blob)?;
+        // The pointer is covered by a base relocation, so it is written relative to the preferred image base.
+        let pointer = endianness
+            .write_uint(
+                image_base + u64::from(rva),
+                layout.pointer.width(pointer_width),
+            )
+            .ok_or(Error::AddressWidth(layout.pointer.width(pointer_width)))?;
+        write(&mut data, pointer_offset, &pointer).ok_or(Error::NotFileBacked { address })?;
+        section_rva = Some(rva);
+    }
+    write(&mut data, size_offset, &size_bytes).ok_or(Error::NotFileBacked { address })?;
+    // Drivers have their checksum verified, other images usually leave it zero.
+    if let Some(nt) = pe_header(&data) {
+        let checksum_offset = nt + 4 + 20 + 64;
+        if read_u32(&data, checksum_offset).is_some_and(|v| v != 0) {
+            let checksum = pe_checksum(&data, checksum_offset);
+            write(&mut data, checksum_offset as u64, &checksum.to_le_bytes());
+        }
+    }
+    Ok(Patched {
+        data,
+        address,
+        section_rva,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        fixtures::{
+            self, extractor, IMAGE_DIRECTORY_ENTRY_SECURITY, PE_CHECKSUM_OFFSET,
+            PE_DATA_DIRECTORIES, PE_SECTION_TABLE,
+        },
+        loader,
+    };
+
+    const IMAGE_BASE: u64 = 0x1_4000_0000;
+    /// The descriptor, at the start of `.data`, the second section.
+    const DESCRIPTOR: u64 = IMAGE_BASE + fixtures::pe_rva(1) as u64 + 0x10;
+    const BLOB: u64 = DESCRIPTOR - 0x10 + 0x100;
+
+    /// A PE driver with a GC descriptor pointing to a 0x40 byte blob.
+    fn driver() -> Vec<u8> {
+        driver_with(&[])
+    }
+
+    fn driver_with(directories: &[(usize, u32, u32)]) -> Vec<u8> {
+        let mut data = vec![0; 0x200];
+        data[0x1C..0x20].copy_from_slice(&0x40u32.to_le_bytes());
+        data[0x30..0x38].copy_from_slice(&BLOB.to_le_bytes());
+        data[0x100..0x140].fill(0xAA);
+        fixtures::pe64(
+            IMAGE_BASE,
+            &[(".text", vec![0xC3; 0x10]), (".data", data)],
+            directories,
+        )
+    }
+
+    fn checksum(data: &[u8]) -> u32 {
+        read_u32(data, PE_CHECKSUM_OFFSET).unwrap()
+    }
+
+    #[test]
+    fn in_place() {
+        let driver = driver();
+        let image = loader::load(&driver, None).unwrap();
+        let blob = [0x55; 0x20];
+        let patched = inject(&image, &driver, &extractor("GC"), DESCRIPTOR, &blob).unwrap();
+        assert_eq!(patched.address, DESCRIPTOR);
+        assert_eq!(patched.section_rva, None);
+        assert_eq!(patched.data.len(), driver.len());
+        // The rest of the old blob is zeroed.
+        let start = image.file_offset(BLOB).unwrap() as usize;
+        assert_eq!(patched.data[start + 0x20..start + 0x40], [0; 0x20]);
+        assert_eq!(
+            checksum(&patched.data),
+            pe_checksum(&patched.data, PE_CHECKSUM_OFFSET)
+        );
+        assert_ne!(checksum(&patched.data), checksum(&driver));
+
+        let image = loader::load(&patched.data, None).unwrap();
+        assert_eq!(
+            extractor("GC").read_fw(&image, DESCRIPTOR).unwrap().data,
+            blob
+        );
+    }
+
+    #[test]
+    fn new_section() {
+        let driver = driver();
+        let image = loader::load(&driver, None).unwrap();
+        let blob: Vec<u8> = (0..0x300).map(|v| v as u8).collect();
+        let patched = inject(&image, &driver, &extractor("GC"), DESCRIPTOR, &blob).unwrap();
+        assert_eq!(patched.section_rva, Some(fixtures::pe_rva(2)));
+        // The original blob is left alone.
+        let start = image.file_offset(BLOB).unwrap() as usize;
+        assert_eq!(patched.data[start..start + 0x40], [0xAA; 0x40]);
+        assert_eq!(
+            checksum(&patched.data),
+            pe_checksum(&patched.data, PE_CHECKSUM_OFFSET)
+        );
+
+        let image = loader::load(&patched.data, None).unwrap();
+        let fw = extractor("GC").read_fw(&image, DESCRIPTOR).unwrap();
+        assert_eq!(fw.fw_off, IMAGE_BASE + u64::from(fixtures::pe_rva(2)));
+        assert_eq!(fw.data, blob);
+    }
+
+    #[test]
+    fn drops_signature() {
+        // Signed drivers end with their certificates, which the security directory points to by file offset.
+        let certificates = driver().len();
+        let mut driver =
+            driver_with(&[(IMAGE_DIRECTORY_ENTRY_SECURITY, certificates as u32, 0x18)]);
+        driver.extend([0x5A; 0x18]);
+        let image = loader::load(&driver, None).unwrap();
+        let blob = vec![0x55; 0x300];
+        let patched = inject(&image, &driver, &extractor("GC"), DESCRIPTOR, &blob).unwrap();
+        // The section takes their place.
+        let raw = read_u32(&patched.data, PE_SECTION_TABLE + 2 * 40 + 20).unwrap();
+        assert_eq!(raw as usize, certificates);
+        assert_eq!(patched.data.len(), certificates + 0x400);
+        let directory = PE_DATA_DIRECTORIES + 8 * IMAGE_DIRECTORY_ENTRY_SECURITY;
+        assert_eq!(patched.data[directory..directory + 8], [0; 8]);
+
+        let image = loader::load(&patched.data, None).unwrap();
+        assert_eq!(
+            extractor("GC").read_fw(&image, DESCRIPTOR).unwrap().data,
+            blob
+        );
+    }
+
+    #[test]
+    fn malformed_headers() {
+        assert!(matches!(align_up(0x10, 0), Err(Error::NoRoomForSection)));
+        assert!(matches!(
+            align_up(u32::MAX, 0x200),
+            Err(Error::NoRoomForSection)
+        ));
+        assert_eq!(align_up(0x201, 0x200).unwrap(), 0x400);
+
+        // A section reaching past the end of the address space.
+        let mut driver = driver();
+        let virtual_size = PE_SECTION_TABLE + 40 + 8;
+        driver[virtual_size..virtual_size + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(matches!(
+            append_pe_section(&mut driver, &[0x55; 0x10]),
+            Err(Error::NoRoomForSection)
+        ));
+    }
+
+    #[test]
+    fn only_pe_grows() {
+        let mut data = vec![0; 0x200];
+        data[0x1C..0x20].copy_from_slice(&0x40u32.to_le_bytes());
+        let object = fixtures::elf(true, data, &[(0x30, 0x100)], &[]);
+        let image = loader::load(&object, None).unwrap();
+        let descriptor = image.segments()[1].address + 0x10;
+        assert!(inject(&image, &object, &extractor("GC"), descriptor, &[0x55; 0x20]).is_ok());
+        assert!(matches!(
+            inject(&image, &object, &extractor("GC"), descriptor, &[0x55; 0x80]),
+            Err(Error::BlobTooLarge {
+                size: 0x80,
+                capacity: 0x40,
+                ..
+            })
+        ));
+    }
+}
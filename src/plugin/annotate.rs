@@ -0,0 +1,70 @@
+//! Copyright © 2024 ChefKiss Inc. Licensed under the Thou Shalt Not Profit License version 1.5.
+//! See LICENSE for details.
+
+use binaryninja::{
+    binaryview::{BinaryView, BinaryViewExt},
+    rc::Ref,
+    symbol::{Symbol, SymbolType},
+    types::{MemberAccess, MemberScope, StructureBuilder, Type},
+};
+
+use crate::{firmware::Firmware, layout::Layout, source::ByteSource};
+
+fn descriptor_type_name(layout: &Layout) -> String {
+    format!("catalyst_{}_descriptor", layout.name.to_ascii_lowercase())
+}
+
+/// Defines the descriptor struct for `layout` and returns a reference to it. Gaps between known fields are left as
+/// padding.
+fn define_descriptor_type(view: &BinaryView, layout: &Layout) -> Ref<Type> {
+    let pointer_width = view.pointer_width();
+    let members = layout.members(pointer_width);
+    let builder = StructureBuilder::new();
+    for member in &members {
+        let ty = if member.pointer {
+            Type::pointer_of_width(&Type::int(1, false), member.width, false, false, None)
+        } else {
+            Type::int(member.width, false)
+        };
+        builder.insert(
+            &ty,
+            member.name.as_str(),
+            member.offset,
+            false,
+            MemberAccess::NoAccess,
+            MemberScope::NoScope,
+        );
+    }
+    builder.set_width(
+        members
+            .iter()
+            .map(|v| v.offset + v.width as u64)
+            .max()
+            .unwrap_or_default(),
+    );
+    let name = descriptor_type_name(layout);
+    let ty = Type::structure(&builder.finalize());
+    view.define_user_type(name.as_str(), &ty);
+    Type::named_type_from_type(name.as_str(), &ty)
+}
+
+fn define_blob(view: &BinaryView, address: u64, len: usize, name: &str) {
+    view.define_user_data_var(address, &Type::array(&Type::int(1, false), len as u64));
+    view.define_user_symbol(&Symbol::builder(SymbolType::Data, name, address).create());
+}
+
+/// Types the descriptor of `fw` and defines named byte arrays over the blobs it points to.
+pub fn annotate(view: &BinaryView, layout: &Layout, fw: &Firmware) {
+    view.define_user_data_var(fw.address, &define_descriptor_type(view, layout));
+    define_blob(view, fw.fw_off, fw.data.len(), &fw.name);
+    let pointer_width = view.pointer_width();
+    for (name, part) in &layout.parts {
+        let (Some(data), Some(ptr)) = (
+            fw.parts.get(name),
+            part.pointer.read(view, fw.address, pointer_width),
+        ) else {
+            continue;
+        };
+        define_blob(view, ptr, data.len(), &format!("{}_{name}", fw.name));
+    }
+}
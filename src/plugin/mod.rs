@@ -0,0 +1,624 @@
+//! Copyright © 2024 ChefKiss Inc. Licensed under the Thou Shalt Not Profit License version 1.5.
+//! See LICENSE for details.
+
+mod annotate;
+mod view;
+
+use std::{
+    fmt,
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use binaryninja::{
+    architecture::CoreArchitecture,
+    binaryview::{BinaryView, BinaryViewBase, BinaryViewExt},
+    command::{register, register_for_address, AddressCommand, Command},
+    interaction,
+    section::Semantics,
+};
+
+use crate::{
+    batch, diff, dump,
+    error::Error,
+    firmware::{Extractor, Firmware, OutputFormat},
+    infer::{self, Pattern},
+    layout::Layout,
+    loader,
+    manifest::Manifest,
+    patch, scan,
+    source::{ByteSource, Endianness, SymbolInfo, SymbolSource},
+    store,
+};
+
+const SCAN_MIN_SCORE: u32 = 4;
+const LAYOUTS_FILE_NAME: &str = "catalyst-fw-layouts.toml";
+
+impl ByteSource for BinaryView {
+    fn read_bytes(&self, offset: u64, len: usize) -> Vec<u8> {
+        self.read_vec(offset, len)
+    }
+
+    fn contains(&self, offset: u64) -> bool {
+        self.offset_valid(offset)
+    }
+
+    fn pointer_width(&self) -> usize {
+        self.address_size()
+    }
+
+    fn endianness(&self) -> Endianness {
+        match self.default_endianness() {
+            binaryninja::Endianness::LittleEndian => Endianness::Little,
+            binaryninja::Endianness::BigEndian => Endianness::Big,
+        }
+    }
+
+    fn file_offset(&self, offset: u64) -> Option<u64> {
+        let segment = self.segment_at(offset)?;
+        let file_offset = offset - segment.address_range().start + segment.parent_range().start;
+        segment
+            .parent_range()
+            .contains(&file_offset)
+            .then_some(file_offset)
+    }
+
+    fn data_ranges(&self) -> Vec<Range<u64>> {
+        let sections = self.sections();
+        if sections.is_empty() {
+            return vec![self.start()..self.start() + self.len() as u64];
+        }
+        sections
+            .iter()
+            .filter(|v| {
+                matches!(
+                    v.semantics(),
+                    Semantics::DefaultSection | Semantics::ReadOnlyData | Semantics::ReadWriteData
+                )
+            })
+            .map(|v| v.start()..v.end())
+            .collect()
+    }
+}
+
+impl SymbolSource for BinaryView {
+    fn symbol_at(&self, offset: u64) -> Option<SymbolInfo> {
+        self.symbol_by_address(offset).ok().map(|v| SymbolInfo {
+            name: v.full_name().as_str().to_owned(),
+            address: v.address(),
+        })
+    }
+}
+
+struct ExtractorCommand(Extractor, OutputFormat);
+
+impl ExtractorCommand {
+    fn new(layout: Layout, format: OutputFormat) -> Self {
+        Self(Extractor::new(layout), format)
+    }
+}
+
+impl AddressCommand for ExtractorCommand {
+    fn valid(&self, view: &BinaryView, addr: u64) -> bool {
+        match self.0.check_fw(view, addr) {
+            Ok(()) => true,
+            Err(e) => {
+                log::debug!(
+                    "{} firmware at {addr:#X} unavailable: {e}",
+                    self.0.layout().name
+                );
+                false
+            }
+        }
+    }
+
+    fn action(&self, view: &BinaryView, addr: u64) {
+        extract(view, &self.0, self.1, addr);
+    }
+}
+
+/// Extracts the firmware at `addr`, annotates it and asks where to save it.
+fn extract(view: &BinaryView, extractor: &Extractor, format: OutputFormat, addr: u64) {
+    let fw = match extractor.read_fw(view, addr) {
+        Ok(v) => v,
+        Err(e) => {
+            report_error(
+                &format!("Extracting {} firmware failed", extractor.layout().name),
+                &e,
+            );
+            return;
+        }
+    };
+    annotate::annotate(view, extractor.layout(), &fw);
+    log::info!(
+        "{}: ucode version {:#x}, feature version {}",
+        fw.name,
+        fw.info.ucode_version,
+        fw.info.feature_version
+    );
+    let Some(path) = rfd::FileDialog::new()
+        .set_file_name(
+            Path::new(&format.file_name(&fw))
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy(),
+        )
+        .set_title(format!("Save {}", fw.name))
+        .save_file()
+    else {
+        return;
+    };
+    let res = format.write(&fw, &path).and_then(|paths| {
+        let mut manifest = driver_manifest(view);
+        manifest.push(&fw, &paths, path.parent().unwrap_or(Path::new("")));
+        manifest.write(&path.with_extension("json"))
+    });
+    let Err(e) = res else {
+        return;
+    };
+    report_error(&format!("Saving {} failed", fw.name), &e);
+}
+
+/// Extracts firmware with the layout inferred from the descriptor's symbol name, or its contents.
+struct InferredExtractorCommand(Vec<Layout>, Vec<Pattern>, OutputFormat);
+
+impl AddressCommand for InferredExtractorCommand {
+    fn valid(&self, view: &BinaryView, addr: u64) -> bool {
+        infer::infer(view, &self.0, &self.1, addr).is_ok()
+    }
+
+    fn action(&self, view: &BinaryView, addr: u64) {
+        match infer::infer(view, &self.0, &self.1, addr) {
+            Ok(layout) => {
+                log::info!("Using the {} layout for {addr:#X}", layout.name);
+                extract(view, &Extractor::new(layout.clone()), self.2, addr);
+            }
+            Err(e) => report_error("Extracting firmware failed", &e),
+        }
+    }
+}
+
+struct DumpCommand(Vec<Layout>, Vec<Pattern>);
+
+impl AddressCommand for DumpCommand {
+    fn valid(&self, view: &BinaryView, addr: u64) -> bool {
+        infer::infer(view, &self.0, &self.1, addr).is_ok()
+    }
+
+    fn action(&self, view: &BinaryView, addr: u64) {
+        let res = infer::infer(view, &self.0, &self.1, addr)
+            .and_then(|v| Extractor::new(v.clone()).read_fw(view, addr));
+        let fw = match res {
+            Ok(v) => v,
+            Err(e) => {
+                report_error("Listing firmware failed", &e);
+                return;
+            }
+        };
+        view.show_plaintext_report(&format!("{} dwords", fw.name), &dump::firmware(&fw));
+    }
+}
+
+/// Path of the file the view was loaded from, or of its database.
+fn driver_path(view: &BinaryView) -> PathBuf {
+    PathBuf::from(view.file().filename().as_str())
+}
+
+/// Name of the driver the view was loaded from, without the extension of its database if it was opened from one.
+fn driver_name(view: &BinaryView) -> String {
+    let path = driver_path(view);
+    let path = match path.extension() {
+        Some(ext) if ext.eq_ignore_ascii_case("bndb") => path.with_extension(""),
+        _ => path,
+    };
+    path.file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// The driver file as loaded, from the raw view underneath `view`, which file offsets refer to. The file on disk may
+/// be a database instead.
+fn driver_data(view: &BinaryView) -> Vec<u8> {
+    let raw = view.parent_view().unwrap_or_else(|_| view.to_owned());
+    raw.read_vec(raw.start(), raw.len())
+}
+
+fn driver_manifest(view: &BinaryView) -> Manifest {
+    Manifest::with_data(&driver_name(view), &driver_data(view))
+}
+
+/// Opens the blob the descriptor at `addr` points to in a child view of the driver, asking for the architecture and
+/// base address.
+fn open_child(view: &BinaryView, extractor: &Extractor, addr: u64) {
+    const TITLE: &str = "Open firmware in new view";
+    let fw = match extractor.read_fw(view, addr) {
+        Ok(v) => v,
+        Err(e) => {
+            report_error("Opening firmware failed", &e);
+            return;
+        }
+    };
+    let len = fw.data.len() as u64;
+    let file_range = fw
+        .file_offset
+        .filter(|&v| len == 0 || view.file_offset(fw.fw_off + len - 1) == Some(v + len - 1))
+        .map(|v| v..v + len);
+    let Some(file_range) = file_range else {
+        log::error!(
+            "{} is not backed by a contiguous range of the driver file",
+            fw.name
+        );
+        return;
+    };
+    let mut archs: Vec<String> = CoreArchitecture::list_all()
+        .iter()
+        .map(|v| v.name().to_string())
+        .collect();
+    let choices: Vec<_> = archs.iter().map(String::as_str).collect();
+    let Some(arch) = interaction::get_choice_input("Architecture", TITLE, &choices) else {
+        return;
+    };
+    let Some(base) = interaction::get_address_input("Base address", TITLE) else {
+        return;
+    };
+    let target = view::Target {
+        name: fw.name,
+        arch: archs.swap_remove(arch),
+        base,
+        file_range,
+    };
+    match view::open(view, &target) {
+        Ok(_) => log::info!(
+            "Opened {} at {base:#X} as {}, switch to it from the view type menu",
+            target.name,
+            view::VIEW_TYPE_NAME
+        ),
+        Err(e) => report_error("Opening firmware failed", &e),
+    }
+}
+
+/// Replaces the firmware at a descriptor with a file and saves a patched copy of the driver.
+struct PatchCommand(Vec<Layout>, Vec<Pattern>);
+
+impl AddressCommand for PatchCommand {
+    fn valid(&self, view: &BinaryView, addr: u64) -> bool {
+        infer::infer(view, &self.0, &self.1, addr).is_ok()
+    }
+
+    fn action(&self, view: &BinaryView, addr: u64) {
+        let layout = match infer::infer(view, &self.0, &self.1, addr) {
+            Ok(v) => v,
+            Err(e) => {
+                report_error("Replacing firmware failed", &e);
+                return;
+            }
+        };
+        let Some(blob) = rfd::FileDialog::new()
+            .set_title("Replacement firmware")
+            .pick_file()
+        else {
+            return;
+        };
+        let res = std::fs::read(&blob).map_err(Error::from).and_then(|blob| {
+            patch::inject(
+                view,
+                &driver_data(view),
+                &Extractor::new(layout.clone()),
+                addr,
+                &blob,
+            )
+        });
+        let patched = match res {
+            Ok(v) => v,
+            Err(e) => {
+                report_error("Replacing firmware failed", &e);
+                return;
+            }
+        };
+        if let Some(rva) = patched.section_rva {
+            log::info!("Moved the firmware to a new section at RVA {rva:#X}");
+        }
+        let Some(path) = rfd::FileDialog::new()
+            .set_title("Save patched driver")
+            .set_file_name(format!("patched_{}", driver_name(view)))
+            .save_file()
+        else {
+            return;
+        };
+        if let Err(e) = std::fs::write(&path, &patched.data) {
+            report_error("Saving patched driver failed", &e);
+        }
+    }
+}
+
+struct OpenCommand(Extractor);
+
+impl AddressCommand for OpenCommand {
+    fn valid(&self, view: &BinaryView, addr: u64) -> bool {
+        self.0.check_fw(view, addr).is_ok()
+    }
+
+    fn action(&self, view: &BinaryView, addr: u64) {
+        open_child(view, &self.0, addr);
+    }
+}
+
+/// Opens firmware in a child view with the descriptor layout inferred from its symbol name, or its contents.
+struct InferredOpenCommand(Vec<Layout>, Vec<Pattern>);
+
+impl AddressCommand for InferredOpenCommand {
+    fn valid(&self, view: &BinaryView, addr: u64) -> bool {
+        infer::infer(view, &self.0, &self.1, addr).is_ok()
+    }
+
+    fn action(&self, view: &BinaryView, addr: u64) {
+        match infer::infer(view, &self.0, &self.1, addr) {
+            Ok(layout) => open_child(view, &Extractor::new(layout.clone()), addr),
+            Err(e) => report_error("Opening firmware failed", &e),
+        }
+    }
+}
+
+fn report_error(title: &str, e: &dyn fmt::Display) {
+    log::error!("{title}: {e}");
+    rfd::MessageDialog::new()
+        .set_level(rfd::MessageLevel::Error)
+        .set_title(title)
+        .set_description(e.to_string())
+        .set_buttons(rfd::MessageButtons::OkCustom("Well, shit".into()))
+        .show();
+}
+
+struct ScanCommand(Vec<Layout>);
+
+impl Command for ScanCommand {
+    fn action(&self, view: &BinaryView) {
+        let candidates = scan::scan(view, &self.0, SCAN_MIN_SCORE);
+        let report = if candidates.is_empty() {
+            "No firmware descriptors found.".to_owned()
+        } else {
+            scan::report(&candidates)
+        };
+        view.show_plaintext_report("Firmware descriptors", &report);
+    }
+
+    fn valid(&self, _view: &BinaryView) -> bool {
+        true
+    }
+}
+
+fn annotate_all(view: &BinaryView, layouts: &[Layout], blobs: &[Firmware]) {
+    for fw in blobs {
+        if let Some(layout) = Layout::find(layouts, &fw.layout) {
+            annotate::annotate(view, layout, fw);
+        }
+    }
+}
+
+struct ListCommand(Vec<Layout>);
+
+impl Command for ListCommand {
+    fn action(&self, view: &BinaryView) {
+        let blobs = batch::collect(view, &self.0, SCAN_MIN_SCORE);
+        let report = if blobs.is_empty() {
+            "No firmware descriptors found.".to_owned()
+        } else {
+            batch::report(&blobs)
+        };
+        view.show_plaintext_report("Firmware versions", &report);
+    }
+
+    fn valid(&self, _view: &BinaryView) -> bool {
+        true
+    }
+}
+
+struct DiffCommand(Vec<Layout>);
+
+impl Command for DiffCommand {
+    fn action(&self, view: &BinaryView) {
+        let Some(path) = rfd::FileDialog::new()
+            .set_title("Driver to compare against")
+            .pick_file()
+        else {
+            return;
+        };
+        // The view's side is mapped from its raw bytes too, so both go through the same loader.
+        let collect = |data: Vec<u8>| {
+            loader::load(&data, None).map(|v| batch::collect(&v, &self.0, SCAN_MIN_SCORE))
+        };
+        let res = std::fs::read(&path)
+            .map_err(Error::from)
+            .and_then(|old| Ok((collect(old)?, collect(driver_data(view))?)));
+        let (old, new) = match res {
+            Ok(v) => v,
+            Err(e) => {
+                report_error("Comparing firmware failed", &e);
+                return;
+            }
+        };
+        view.show_plaintext_report(
+            "Firmware differences",
+            &diff::report(&diff::diff(&old, &new)),
+        );
+    }
+
+    fn valid(&self, _view: &BinaryView) -> bool {
+        true
+    }
+}
+
+struct AnnotateCommand(Vec<Layout>);
+
+impl Command for AnnotateCommand {
+    fn action(&self, view: &BinaryView) {
+        let blobs = batch::collect(view, &self.0, SCAN_MIN_SCORE);
+        log::info!("Annotating {} firmware descriptors", blobs.len());
+        annotate_all(view, &self.0, &blobs);
+    }
+
+    fn valid(&self, _view: &BinaryView) -> bool {
+        true
+    }
+}
+
+struct ExtractAllCommand(Vec<Layout>, OutputFormat);
+
+impl Command for ExtractAllCommand {
+    fn action(&self, view: &BinaryView) {
+        let blobs = batch::collect(view, &self.0, SCAN_MIN_SCORE);
+        if blobs.is_empty() {
+            log::info!("No firmware descriptors found");
+            rfd::MessageDialog::new()
+                .set_level(rfd::MessageLevel::Info)
+                .set_title("Extract all firmware")
+                .set_description("No firmware descriptors found.")
+                .show();
+            return;
+        }
+        annotate_all(view, &self.0, &blobs);
+        let Some(dir) = rfd::FileDialog::new()
+            .set_title(format!("Save {} firmware blobs", blobs.len()))
+            .pick_folder()
+        else {
+            return;
+        };
+        if let Err(e) = batch::write_all(&blobs, &dir, self.1, driver_manifest(view)) {
+            report_error("Saving firmware failed", &e);
+        }
+    }
+
+    fn valid(&self, _view: &BinaryView) -> bool {
+        true
+    }
+}
+
+struct StoreCommand(Vec<Layout>);
+
+impl Command for StoreCommand {
+    fn action(&self, view: &BinaryView) {
+        let blobs = batch::collect(view, &self.0, SCAN_MIN_SCORE);
+        let Some(dir) = rfd::FileDialog::new()
+            .set_title("Firmware store")
+            .pick_folder()
+        else {
+            return;
+        };
+        match store::add(&blobs, &dir, OutputFormat::Raw, driver_manifest(view)) {
+            Ok(v) => log::info!(
+                "Stored {} firmware blobs, {} of {} files were new",
+                blobs.len(),
+                v.new_objects,
+                v.objects
+            ),
+            Err(e) => report_error("Storing firmware failed", &e),
+        }
+    }
+
+    fn valid(&self, _view: &BinaryView) -> bool {
+        true
+    }
+}
+
+fn load_layouts() -> (Vec<Layout>, Vec<Pattern>) {
+    let builtin = || (Layout::builtin(), Pattern::builtin());
+    let Ok(dir) = binaryninja::user_directory() else {
+        return builtin();
+    };
+    let path = dir.join(LAYOUTS_FILE_NAME);
+    if !path.exists() {
+        return builtin();
+    }
+    Layout::load(&path)
+        .and_then(|v| Ok((v, Pattern::load(&path)?)))
+        .unwrap_or_else(|e| {
+            log::error!("{e}");
+            builtin()
+        })
+}
+
+#[no_mangle]
+pub extern "C" fn CorePluginInit() -> bool {
+    let _ = binaryninja::logger::init(log::LevelFilter::Info);
+    let (layouts, patterns) = load_layouts();
+    view::register();
+    register_for_address(
+        "ChefKiss\\Extract firmware",
+        "Extracts the firmware with the descriptor layout inferred from its symbol name or contents",
+        InferredExtractorCommand(layouts.clone(), patterns.clone(), OutputFormat::Raw),
+    );
+    register_for_address(
+        "ChefKiss\\Extract firmware (amdgpu)",
+        "Extracts the firmware with amdgpu headers, inferring the descriptor layout",
+        InferredExtractorCommand(layouts.clone(), patterns.clone(), OutputFormat::Amdgpu),
+    );
+    register_for_address(
+        "ChefKiss\\List firmware dwords",
+        "Lists the firmware dword by dword, with its jump table",
+        DumpCommand(layouts.clone(), patterns.clone()),
+    );
+    register_for_address(
+        "ChefKiss\\Open firmware in new view",
+        "Maps the firmware into a view of its own, analysed as the chosen architecture",
+        InferredOpenCommand(layouts.clone(), patterns.clone()),
+    );
+    register_for_address(
+        "ChefKiss\\Replace firmware",
+        "Replaces the firmware with a file, moving it to a new section if it is larger, and saves a patched driver",
+        PatchCommand(layouts.clone(), patterns),
+    );
+    for layout in &layouts {
+        register_for_address(
+            format!("ChefKiss\\Extract {} firmware", layout.name).as_str(),
+            "",
+            ExtractorCommand::new(layout.clone(), OutputFormat::Raw),
+        );
+        register_for_address(
+            format!("ChefKiss\\Extract {} firmware (amdgpu)", layout.name).as_str(),
+            "",
+            ExtractorCommand::new(layout.clone(), OutputFormat::Amdgpu),
+        );
+        register_for_address(
+            format!("ChefKiss\\Open {} firmware in new view", layout.name).as_str(),
+            "",
+            OpenCommand(Extractor::new(layout.clone())),
+        );
+    }
+    register(
+        "ChefKiss\\Scan for firmware descriptors",
+        "Lists every location that looks like a firmware descriptor",
+        ScanCommand(layouts.clone()),
+    );
+    register(
+        "ChefKiss\\List firmware versions",
+        "Lists every discovered firmware blob with its ASIC and ucode versions",
+        ListCommand(layouts.clone()),
+    );
+    register(
+        "ChefKiss\\Compare firmware with another driver",
+        "Lists the firmware added, removed or changed relative to another driver image",
+        DiffCommand(layouts.clone()),
+    );
+    register(
+        "ChefKiss\\Annotate firmware descriptors",
+        "Types every discovered firmware descriptor and names the blobs it points to",
+        AnnotateCommand(layouts.clone()),
+    );
+    register(
+        "ChefKiss\\Extract all firmware",
+        "Saves every discovered firmware blob into a directory",
+        ExtractAllCommand(layouts.clone(), OutputFormat::Raw),
+    );
+    register(
+        "ChefKiss\\Extract all firmware (amdgpu)",
+        "Saves every discovered firmware blob into a directory with amdgpu headers",
+        ExtractAllCommand(layouts.clone(), OutputFormat::Amdgpu),
+    );
+    register(
+        "ChefKiss\\Add all firmware to store",
+        "Saves every discovered firmware blob into a content-addressed store, indexed by driver",
+        StoreCommand(layouts),
+    );
+    true
+}
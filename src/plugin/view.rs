@@ -0,0 +1,191 @@
+//! Copyright © 2024 ChefKiss Inc. Licensed under the Thou Shalt Not Profit License version 1.5.
+//! See LICENSE for details.
+
+use std::{ops::Range, sync::OnceLock};
+
+use binaryninja::{
+    architecture::{Architecture, ArchitectureExt, CoreArchitecture},
+    binaryview::{BinaryView, BinaryViewBase, BinaryViewExt},
+    custombinaryview::{
+        register_view_type, BinaryViewType, BinaryViewTypeBase, BinaryViewTypeExt,
+        CustomBinaryView, CustomBinaryViewType, CustomView, CustomViewBuilder,
+    },
+    rc::Ref,
+    section::{Section, Semantics},
+    segment::Segment,
+    symbol::{Symbol, SymbolType},
+    Endianness,
+};
+
+pub const VIEW_TYPE_NAME: &str = "CatalystFirmware";
+
+/// Metadata of the driver's raw view handing the blob to open over to the firmware view, as view types are
+/// instantiated by Binary Ninja without arguments. It is auto metadata, so it never ends up in a database, and it is
+/// removed as soon as the view is created.
+const NAME_KEY: &str = "catalyst_fw.name";
+const ARCH_KEY: &str = "catalyst_fw.arch";
+const BASE_KEY: &str = "catalyst_fw.base";
+const FILE_START_KEY: &str = "catalyst_fw.file_start";
+const FILE_END_KEY: &str = "catalyst_fw.file_end";
+const KEYS: [&str; 5] = [NAME_KEY, ARCH_KEY, BASE_KEY, FILE_START_KEY, FILE_END_KEY];
+
+/// A firmware blob to map into its own view.
+#[derive(Debug, Clone)]
+pub struct Target {
+    pub name: String,
+    pub arch: String,
+    pub base: u64,
+    /// Where the blob is in the driver file.
+    pub file_range: Range<u64>,
+}
+
+impl Target {
+    /// Reads the target stored on `data`, removing it.
+    fn take(data: &BinaryView) -> Option<Self> {
+        let string = |key| Some(data.query_metadata(key)?.get_string().ok()?.to_string());
+        let integer = |key| data.query_metadata(key)?.get_unsigned_integer().ok();
+        let ret = Some(Self {
+            name: string(NAME_KEY)?,
+            arch: string(ARCH_KEY)?,
+            base: integer(BASE_KEY)?,
+            file_range: integer(FILE_START_KEY)?..integer(FILE_END_KEY)?,
+        });
+        for key in KEYS {
+            data.remove_metadata(key);
+        }
+        ret
+    }
+
+    fn store(&self, data: &BinaryView) {
+        data.store_metadata(NAME_KEY, self.name.as_str(), true);
+        data.store_metadata(ARCH_KEY, self.arch.as_str(), true);
+        data.store_metadata(BASE_KEY, self.base, true);
+        data.store_metadata(FILE_START_KEY, self.file_range.start, true);
+        data.store_metadata(FILE_END_KEY, self.file_range.end, true);
+    }
+}
+
+pub struct FirmwareView {
+    handle: Ref<BinaryView>,
+    arch: CoreArchitecture,
+}
+
+impl AsRef<BinaryView> for FirmwareView {
+    fn as_ref(&self) -> &BinaryView {
+        &self.handle
+    }
+}
+
+impl BinaryViewBase for FirmwareView {
+    fn entry_point(&self) -> u64 {
+        self.handle.start()
+    }
+
+    fn default_endianness(&self) -> Endianness {
+        self.arch.endianness()
+    }
+
+    fn address_size(&self) -> usize {
+        self.arch.address_size()
+    }
+}
+
+unsafe impl CustomBinaryView for FirmwareView {
+    type Args = Target;
+
+    fn new(handle: &BinaryView, args: &Self::Args) -> binaryninja::binaryview::Result<Self> {
+        Ok(Self {
+            handle: handle.to_owned(),
+            arch: CoreArchitecture::by_name(&args.arch).ok_or(())?,
+        })
+    }
+
+    fn init(&self, target: Self::Args) -> binaryninja::binaryview::Result<()> {
+        let len = target.file_range.end - target.file_range.start;
+        let range = target.base..target.base.checked_add(len).ok_or(())?;
+        self.handle.add_segment(
+            Segment::builder(range.clone())
+                .parent_range(target.file_range.clone())
+                .is_auto(true)
+                .readable(true)
+                .executable(true)
+                .contains_code(true)
+                .contains_data(true),
+        );
+        self.handle.add_section(
+            Section::builder(target.name.as_str(), range.clone())
+                .semantics(Semantics::ReadOnlyCode)
+                .is_auto(true),
+        );
+        self.handle.set_default_arch(&self.arch);
+        let platform = self.arch.standalone_platform().ok_or(())?;
+        self.handle.set_default_platform(&platform);
+        self.handle.define_auto_symbol(
+            &Symbol::builder(
+                SymbolType::Function,
+                &format!("{}_start", target.name),
+                target.base,
+            )
+            .create(),
+        );
+        self.handle.add_entry_point(&platform, target.base);
+        Ok(())
+    }
+}
+
+pub struct FirmwareViewType {
+    handle: BinaryViewType,
+}
+
+impl AsRef<BinaryViewType> for FirmwareViewType {
+    fn as_ref(&self) -> &BinaryViewType {
+        &self.handle
+    }
+}
+
+impl BinaryViewTypeBase for FirmwareViewType {
+    /// Only ever created on request, never offered for files being opened.
+    fn is_valid_for(&self, _data: &BinaryView) -> bool {
+        false
+    }
+}
+
+impl CustomBinaryViewType for FirmwareViewType {
+    fn create_custom_view<'builder>(
+        &self,
+        data: &BinaryView,
+        builder: CustomViewBuilder<'builder, Self>,
+    ) -> binaryninja::binaryview::Result<CustomView<'builder>> {
+        let target = Target::take(data).ok_or(())?;
+        builder.create::<FirmwareView>(data, target)
+    }
+}
+
+static VIEW_TYPE: OnceLock<&'static FirmwareViewType> = OnceLock::new();
+
+pub fn register() {
+    let view_type = register_view_type(VIEW_TYPE_NAME, "Catalyst Firmware", |handle| {
+        FirmwareViewType { handle }
+    });
+    let _ = VIEW_TYPE.set(view_type);
+}
+
+/// Maps `target` over the raw data of `view` as a Catalyst Firmware child view of the same file, without copying it
+/// out, selectable from the view type menu of its tab, and starts analysing it in the background. Opening another blob
+/// replaces the previous one.
+pub fn open(view: &BinaryView, target: &Target) -> Result<Ref<BinaryView>, String> {
+    let view_type = VIEW_TYPE
+        .get()
+        .ok_or("The firmware view type is not registered")?;
+    let raw = view.parent_view().unwrap_or_else(|_| view.to_owned());
+    target.store(&raw);
+    let ret = view_type.create(&raw).map_err(|()| {
+        // Not left behind for a later view if creation failed before taking it.
+        for key in KEYS {
+            raw.remove_metadata(key);
+        }
+        format!("Failed to open {} as {}", target.name, target.arch)
+    })?;
+    ret.update_analysis();
+    Ok(ret)
+}
@@ -0,0 +1,299 @@
+//! Copyright © 2024 ChefKiss Inc. Licensed under the Thou Shalt Not Profit License version 1.5.
+//! See LICENSE for details.
+
+use std::ops::Range;
+
+use crate::{
+    firmware::{Extractor, FirmwareType},
+    image::Image,
+    layout::Layout,
+    source::{ByteSource, SymbolSource},
+};
+
+const DESCRIPTOR_ALIGN: u64 = 4;
+const MIN_FW_SIZE: u32 = 0x100;
+const MAX_FW_SIZE: u32 = 0x100_0000;
+
+#[derive(Debug, Clone)]
+pub struct Candidate<'a> {
+    pub address: u64,
+    pub layout: &'a Layout,
+    pub fw_off: u64,
+    pub fw_size: u32,
+    pub symbol: Option<String>,
+    pub score: u32,
+}
+
+fn name_hint(ty: FirmwareType, name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    match ty {
+        FirmwareType::Gc => ["gfx", "pfp", "_me", "_ce", "mec", "ucode"]
+            .iter()
+            .any(|v| name.contains(v)),
+        FirmwareType::Sdma => name.contains("sdma"),
+        FirmwareType::Rlc => name.contains("rlc"),
+    }
+}
+
+fn score<S: ByteSource + SymbolSource + ?Sized>(
+    src: &S,
+    extractor: &Extractor,
+    candidate: &mut Candidate,
+) {
+    let mut score = 0;
+    let head = src.read_bytes(candidate.fw_off, 0x10);
+    if head.iter().any(|&v| v != head[0]) {
+        score += 2;
+    }
+    if candidate.fw_size.is_multiple_of(4) {
+        score += 2;
+    }
+    if (0x400..=0x8_0000).contains(&candidate.fw_size) {
+        score += 2;
+    }
+    if candidate.fw_off.is_multiple_of(4) {
+        score += 1;
+    }
+    if candidate.fw_off.is_multiple_of(0x100) {
+        score += 1;
+    }
+    if !candidate.layout.parts.is_empty()
+        && extractor.read_parts(src, candidate.address).len() == candidate.layout.parts.len()
+    {
+        score += 2;
+    }
+    if let Some(sym) = src.symbol_at(candidate.address) {
+        score += if name_hint(candidate.layout.ty, &sym.name) {
+            4
+        } else {
+            1
+        };
+        if sym
+            .name
+            .to_ascii_lowercase()
+            .contains(&candidate.layout.name.to_ascii_lowercase())
+        {
+            score += 2;
+        }
+        candidate.symbol = Some(sym.name);
+    }
+    candidate.score = score;
+}
+
+fn in_data(ranges: &[Range<u64>], start: u64, len: u32) -> bool {
+    let Some(end) = start.checked_add(u64::from(len)) else {
+        return false;
+    };
+    ranges.iter().any(|v| v.start <= start && end <= v.end)
+}
+
+/// Whether a descriptor at `address` pointing to `fw_size` bytes at `fw_off` could be genuine.
+fn plausible(
+    layout: &Layout,
+    pointer_width: usize,
+    ranges: &[Range<u64>],
+    address: u64,
+    fw_off: u64,
+    fw_size: u32,
+) -> bool {
+    fw_off != 0
+        && (MIN_FW_SIZE..=MAX_FW_SIZE).contains(&fw_size)
+        && !(address..address + layout.descriptor_size(pointer_width)).contains(&fw_off)
+        && in_data(ranges, fw_off, fw_size)
+}
+
+/// Scores `layout` for the descriptor at `address`, if it yields a plausible blob.
+pub fn candidate<'a, S: ByteSource + SymbolSource + ?Sized>(
+    src: &S,
+    layout: &'a Layout,
+    address: u64,
+) -> Option<Candidate<'a>> {
+    rate(src, layout, address).filter(|v| {
+        plausible(
+            layout,
+            src.pointer_width(),
+            &src.data_ranges(),
+            address,
+            v.fw_off,
+            v.fw_size,
+        )
+    })
+}
+
+/// Scores `layout` for the descriptor at `address` however small or out of place its blob is, for when the address is
+/// known to hold a descriptor.
+pub fn rate<'a, S: ByteSource + SymbolSource + ?Sized>(
+    src: &S,
+    layout: &'a Layout,
+    address: u64,
+) -> Option<Candidate<'a>> {
+    let extractor = Extractor::new(layout.clone());
+    let (fw_off, fw_size) = extractor.read_fw_info(src, address).ok()?;
+    let mut ret = Candidate {
+        address,
+        layout,
+        fw_off,
+        fw_size,
+        symbol: None,
+        score: 0,
+    };
+    score(src, &extractor, &mut ret);
+    Some(ret)
+}
+
+/// Tries every firmware layout at every aligned address of the data ranges, best candidates first.
+pub fn scan<'a, S: ByteSource + SymbolSource + ?Sized>(
+    src: &S,
+    layouts: &'a [Layout],
+    min_score: u32,
+) -> Vec<Candidate<'a>> {
+    let pointer_width = src.pointer_width();
+    let endianness = src.endianness();
+    let ranges = src.data_ranges();
+    let extractors: Vec<_> = layouts.iter().cloned().map(Extractor::new).collect();
+    let mut ret = Vec::new();
+    for range in ranges.iter().cloned() {
+        let Ok(len) = usize::try_from(range.end - range.start) else {
+            continue;
+        };
+        let window = Image::flat(
+            range.start,
+            src.read_bytes(range.start, len),
+            pointer_width,
+            endianness,
+        );
+        let mut address = range.start.next_multiple_of(DESCRIPTOR_ALIGN);
+        while address < range.end {
+            for (layout, extractor) in layouts.iter().zip(&extractors) {
+                let Ok((fw_off, fw_size)) = extractor.read_fw_info(&window, address) else {
+                    continue;
+                };
+                if !plausible(layout, pointer_width, &ranges, address, fw_off, fw_size) {
+                    continue;
+                }
+                let mut candidate = Candidate {
+                    address,
+                    layout,
+                    fw_off,
+                    fw_size,
+                    symbol: None,
+                    score: 0,
+                };
+                score(src, extractor, &mut candidate);
+                if candidate.score >= min_score {
+                    ret.push(candidate);
+                }
+            }
+            address += DESCRIPTOR_ALIGN;
+        }
+    }
+    ret.sort_by(|a, b| b.score.cmp(&a.score).then(a.address.cmp(&b.address)));
+    ret
+}
+
+pub fn report(candidates: &[Candidate<'_>]) -> String {
+    let mut ret = String::new();
+    for v in candidates {
+        ret += &format!(
+            "{:#010X} {:<8} score {:>2}: {:#X} bytes at {:#X}",
+            v.address, v.layout.name, v.score, v.fw_size, v.fw_off
+        );
+        if let Some(sym) = &v.symbol {
+            ret += &format!(" ({sym})");
+        }
+        ret.push('\n');
+    }
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{image::SegmentKind, source::Endianness};
+
+    const DATA: u64 = 0x10000;
+    const BLOB: u64 = 0x10400;
+
+    fn descriptor(data: &mut [u8], at: usize, size: u32, pointer: u64) {
+        data[at + 0xC..at + 0x10].copy_from_slice(&size.to_le_bytes());
+        data[at + 0x20..at + 0x28].copy_from_slice(&pointer.to_le_bytes());
+    }
+
+    fn image(data: Vec<u8>) -> Image {
+        let mut ret = Image::new(0, 8, Endianness::Little);
+        let mut code = vec![0; 0x100];
+        descriptor(&mut code, 0, 0x400, BLOB);
+        ret.add_segment(0x1000, code, SegmentKind::Code, None);
+        ret.add_segment(DATA, data, SegmentKind::Data, None);
+        ret
+    }
+
+    fn data() -> Vec<u8> {
+        let mut ret = vec![0; 0x1000];
+        for (i, v) in ret[(BLOB - DATA) as usize..].iter_mut().enumerate() {
+            *v = (i * 7) as u8;
+        }
+        ret
+    }
+
+    #[test]
+    fn finds_descriptor() {
+        let mut data = data();
+        descriptor(&mut data, 0x40, 0x400, BLOB);
+        let mut image = image(data);
+        image.add_symbol(DATA + 0x40, "_gfx_pfp_ucode");
+
+        let layouts = Layout::builtin();
+        let found = scan(&image, &layouts, 4);
+        let best = &found[0];
+        assert_eq!(best.address, DATA + 0x40);
+        assert_eq!(best.layout.name, "GC");
+        assert_eq!((best.fw_off, best.fw_size), (BLOB, 0x400));
+        assert_eq!(best.symbol.as_deref(), Some("_gfx_pfp_ucode"));
+        // 2 varied contents, 2 dword size, 2 typical size, 2 alignment, 4 name hint.
+        assert_eq!(best.score, 12);
+        // Descriptors in code are never looked at.
+        assert!(found.iter().all(|v| v.address >= DATA));
+    }
+
+    #[test]
+    fn rejects_implausible() {
+        let layouts = Layout::builtin();
+        for (size, pointer) in [
+            // Too small.
+            (0x10, BLOB),
+            // Runs past the data.
+            (0x2000, BLOB),
+            // Points into code.
+            (0x400, 0x1000),
+            // Points into its own descriptor.
+            (0x400, DATA + 0x40),
+        ] {
+            let mut data = data();
+            descriptor(&mut data, 0x40, size, pointer);
+            let image = image(data);
+            assert!(
+                scan(&image, &layouts, 0)
+                    .iter()
+                    .all(|v| v.address != DATA + 0x40 || v.layout.name != "GC"),
+                "{size:#x} bytes at {pointer:#x}"
+            );
+            assert!(candidate(&image, &layouts[0], DATA + 0x40).is_none());
+        }
+    }
+
+    #[test]
+    fn min_score() {
+        let mut data = data();
+        descriptor(&mut data, 0x40, 0x400, BLOB);
+        let image = image(data);
+        let layouts = Layout::builtin();
+        let score = candidate(&image, &layouts[0], DATA + 0x40).unwrap().score;
+        assert!(scan(&image, &layouts, score)
+            .iter()
+            .any(|v| v.address == DATA + 0x40));
+        assert!(scan(&image, &layouts, score + 1)
+            .iter()
+            .all(|v| v.address != DATA + 0x40));
+    }
+}
@@ -0,0 +1,88 @@
+//! Copyright © 2024 ChefKiss Inc. Licensed under the Thou Shalt Not Profit License version 1.5.
+//! See LICENSE for details.
+
+use std::ops::Range;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Endianness {
+    Little,
+    Big,
+}
+
+impl Endianness {
+    pub fn read_u16(self, data: &[u8]) -> Option<u16> {
+        let data = data.try_into().ok()?;
+        Some(match self {
+            Self::Little => u16::from_le_bytes(data),
+            Self::Big => u16::from_be_bytes(data),
+        })
+    }
+
+    pub fn read_u32(self, data: &[u8]) -> Option<u32> {
+        let data = data.try_into().ok()?;
+        Some(match self {
+            Self::Little => u32::from_le_bytes(data),
+            Self::Big => u32::from_be_bytes(data),
+        })
+    }
+
+    /// Reads a 4 or 8 byte pointer, zero-extended to 64 bits.
+    pub fn read_ptr(self, data: &[u8]) -> Option<u64> {
+        match data.len() {
+            4 => self.read_u32(data).map(u64::from),
+            8 => self.read_u64(data),
+            _ => None,
+        }
+    }
+
+    /// Reads a 1, 2, 4 or 8 byte unsigned integer, zero-extended to 64 bits.
+    pub fn read_uint(self, data: &[u8]) -> Option<u64> {
+        match data.len() {
+            1 => Some(data[0].into()),
+            2 => self.read_u16(data).map(u64::from),
+            _ => self.read_ptr(data),
+        }
+    }
+
+    /// Encodes `value` as a 1, 2, 4 or 8 byte unsigned integer, `None` if it doesn't fit.
+    pub fn write_uint(self, value: u64, width: usize) -> Option<Vec<u8>> {
+        if !matches!(width, 1 | 2 | 4 | 8) || (width < 8 && value >> (width * 8) != 0) {
+            return None;
+        }
+        Some(match self {
+            Self::Little => value.to_le_bytes()[..width].to_vec(),
+            Self::Big => value.to_be_bytes()[8 - width..].to_vec(),
+        })
+    }
+
+    pub fn read_u64(self, data: &[u8]) -> Option<u64> {
+        let data = data.try_into().ok()?;
+        Some(match self {
+            Self::Little => u64::from_le_bytes(data),
+            Self::Big => u64::from_be_bytes(data),
+        })
+    }
+}
+
+/// Something descriptors and firmware blobs can be read out of.
+pub trait ByteSource {
+    /// Reads up to `len` bytes at `offset`. Short reads return fewer bytes.
+    fn read_bytes(&self, offset: u64, len: usize) -> Vec<u8>;
+    fn contains(&self, offset: u64) -> bool;
+    fn pointer_width(&self) -> usize;
+    fn endianness(&self) -> Endianness;
+    /// Offset of `offset` in the file backing the source, if any.
+    fn file_offset(&self, offset: u64) -> Option<u64>;
+    /// Address ranges holding initialised data, i.e. where descriptors and blobs may live.
+    fn data_ranges(&self) -> Vec<Range<u64>>;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SymbolInfo {
+    pub name: String,
+    pub address: u64,
+}
+
+pub trait SymbolSource {
+    fn symbol_at(&self, offset: u64) -> Option<SymbolInfo>;
+}
@@ -0,0 +1,212 @@
+//! Copyright © 2024 ChefKiss Inc. Licensed under the Thou Shalt Not Profit License version 1.5.
+//! See LICENSE for details.
+
+use std::path::{Path, PathBuf};
+
+use crate::{
+    error::{Error, Result},
+    firmware::{Firmware, OutputFormat},
+    manifest::{sha256_hex, Entry, Manifest},
+};
+
+pub const OBJECTS_DIR_NAME: &str = "objects";
+pub const DRIVERS_DIR_NAME: &str = "drivers";
+/// Hex digits a hash prefix needs, so that it doesn't match most of the store.
+pub const MIN_HASH_PREFIX: usize = 4;
+
+/// Where a file with the given SHA-256 lives, relative to the store: `objects/<first two digits>/<hash>.bin`.
+pub fn object_path(hash: &str) -> PathBuf {
+    Path::new(OBJECTS_DIR_NAME)
+        .join(&hash[..2])
+        .join(format!("{hash}.bin"))
+}
+
+#[derive(Debug, Clone)]
+pub struct Stored {
+    /// The driver's index, a manifest whose files point into `objects`.
+    pub index: PathBuf,
+    pub objects: usize,
+    /// Objects that were not in the store yet.
+    pub new_objects: usize,
+}
+
+/// Adds the files `format` produces for every blob to the store in `dir`, writing each distinct file once, and indexes
+/// them in `manifest`, describing the driver, under its SHA-256, or its name when it couldn't be read. Indices of amdgpu
+/// output are kept apart from raw ones, as their files differ.
+pub fn add(
+    blobs: &[Firmware],
+    dir: &Path,
+    format: OutputFormat,
+    mut manifest: Manifest,
+) -> Result<Stored> {
+    for blob in blobs {
+        format.check(blob)?;
+    }
+    let mut objects = 0;
+    let mut new_objects = 0;
+    for blob in blobs {
+        let mut paths = Vec::new();
+        for (_, data) in format.encode(blob)? {
+            let path = dir.join(object_path(&sha256_hex(&data)));
+            objects += 1;
+            if !path.exists() {
+                if let Some(parent) = path.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                std::fs::write(&path, data)?;
+                new_objects += 1;
+            }
+            paths.push(path);
+        }
+        manifest.push(blob, &paths, dir);
+    }
+    let name = manifest
+        .driver_sha256
+        .clone()
+        .or_else(|| manifest.driver.clone())
+        .unwrap_or_else(|| "unknown".to_owned());
+    let suffix = match format {
+        OutputFormat::Raw => "",
+        OutputFormat::Amdgpu => "_amdgpu",
+    };
+    let index = dir
+        .join(DRIVERS_DIR_NAME)
+        .join(format!("{name}{suffix}.json"));
+    std::fs::create_dir_all(dir.join(DRIVERS_DIR_NAME))?;
+    manifest.write(&index)?;
+    Ok(Stored {
+        index,
+        objects,
+        new_objects,
+    })
+}
+
+/// Every driver index in the store.
+pub fn indices(dir: &Path) -> Result<Vec<Manifest>> {
+    let dir = dir.join(DRIVERS_DIR_NAME);
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut paths = std::fs::read_dir(dir)?
+        .map(|v| v.map(|v| v.path()))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    paths.retain(|v| v.extension().is_some_and(|v| v == "json"));
+    paths.sort();
+    paths.iter().map(|v| Manifest::read(v)).collect()
+}
+
+/// The drivers shipping a blob whose SHA-256 starts with `hash`, either the ucode itself or one of the files stored for
+/// it, along with what they call it. `hash` must have at least `MIN_HASH_PREFIX` hex digits.
+pub fn find(dir: &Path, hash: &str) -> Result<Vec<(Manifest, Entry)>> {
+    if !(MIN_HASH_PREFIX..=64).contains(&hash.len()) || !hash.bytes().all(|v| v.is_ascii_hexdigit())
+    {
+        return Err(Error::InvalidHashPrefix(hash.to_owned()));
+    }
+    let hash = hash.to_ascii_lowercase();
+    let matches = |entry: &Entry| {
+        entry.sha256.starts_with(&hash)
+            || entry.files.iter().any(|v| {
+                Path::new(v)
+                    .file_stem()
+                    .is_some_and(|v| v.to_string_lossy().starts_with(&hash))
+            })
+    };
+    let mut ret = Vec::new();
+    for index in indices(dir)? {
+        for entry in index.firmware.iter().filter(|v| matches(v)) {
+            ret.push((index.clone(), entry.clone()));
+        }
+    }
+    Ok(ret)
+}
+
+pub fn report(hits: &[(Manifest, Entry)]) -> String {
+    let mut ret = String::new();
+    for (index, entry) in hits {
+        ret += &format!(
+            "{:<24} {:<24} {:#010X} {}\n",
+            index.driver.as_deref().unwrap_or("-"),
+            entry.name,
+            entry.descriptor_address,
+            entry.sha256
+        );
+    }
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{self, firmware};
+
+    #[test]
+    fn hash_prefix() {
+        let dir = Path::new("/nonexistent");
+        for hash in ["", "abc", "abcg", "ab cd", &"a".repeat(65)] {
+            assert!(
+                matches!(find(dir, hash), Err(Error::InvalidHashPrefix(_))),
+                "{hash:?}"
+            );
+        }
+        for hash in ["abcd", "ABCD", &"a".repeat(64)] {
+            assert!(find(dir, hash).unwrap().is_empty(), "{hash:?}");
+        }
+    }
+
+    #[test]
+    fn shares_objects_between_drivers() {
+        let dir = fixtures::temp_dir("store");
+        let shared = firmware("Hawaii_mec", 0x1000, vec![1; 0x10]);
+        let old = [shared.clone(), firmware("Hawaii_me", 0x1040, vec![2; 0x10])];
+        let new = [firmware("Hawaii_me", 0x2040, vec![3; 0x10]), shared];
+
+        let stored = add(
+            &old,
+            &dir,
+            OutputFormat::Raw,
+            Manifest::with_data("old.sys", b"old"),
+        )
+        .unwrap();
+        assert_eq!((stored.objects, stored.new_objects), (2, 2));
+        // Only the new `Hawaii_me` is written again.
+        let stored = add(
+            &new,
+            &dir,
+            OutputFormat::Raw,
+            Manifest::with_data("new.sys", b"new"),
+        )
+        .unwrap();
+        assert_eq!((stored.objects, stored.new_objects), (2, 1));
+        assert_eq!(
+            stored.index,
+            dir.join(DRIVERS_DIR_NAME)
+                .join(format!("{}.json", sha256_hex(b"new")))
+        );
+        let objects = std::fs::read_dir(dir.join(OBJECTS_DIR_NAME))
+            .unwrap()
+            .map(|v| std::fs::read_dir(v.unwrap().path()).unwrap().count())
+            .sum::<usize>();
+        assert_eq!(objects, 3);
+
+        let hash = sha256_hex(&[1; 0x10]);
+        let hits = find(&dir, &hash[..8]).unwrap();
+        let mut drivers: Vec<_> = hits
+            .iter()
+            .map(|(index, entry)| (index.driver.clone().unwrap(), entry.descriptor_address))
+            .collect();
+        drivers.sort();
+        assert_eq!(
+            drivers,
+            [
+                ("new.sys".to_owned(), 0x1000),
+                ("old.sys".to_owned(), 0x1000)
+            ]
+        );
+        assert_eq!(
+            hits[0].1.files,
+            [object_path(&hash).to_string_lossy().replace('\\', "/")]
+        );
+        assert!(find(&dir, &sha256_hex(&[4; 0x10])).unwrap().is_empty());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}